@@ -1,7 +1,31 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+const PROTOS: [&str; 3] = [
+    "../protos/functional_units.proto",
+    "../protos/health.proto",
+    "../protos/reflection.proto",
+];
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::configure()
-        .build_client(false)
         .out_dir("src/server")
-        .compile(&["../protos/functional_units.proto"], &["../protos"])?;
+        .compile(&PROTOS, &["../protos"])?;
+
+    // Descriptors of the same protos served through server reflection
+    let descriptors = PathBuf::from(env::var("OUT_DIR")?).join("descriptors.bin");
+    let status = Command::new(prost_build::protoc())
+        .arg("--include_imports")
+        .arg("-I../protos")
+        .arg("-I")
+        .arg(prost_build::protoc_include())
+        .arg("-o")
+        .arg(&descriptors)
+        .args(PROTOS.iter())
+        .status()?;
+    if !status.success() {
+        return Err(format!("protoc failed with {}", status).into());
+    }
     Ok(())
 }
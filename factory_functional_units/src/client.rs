@@ -0,0 +1,1162 @@
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use prost::Message;
+use tokio::sync::mpsc;
+use tonic::metadata::AsciiMetadataValue;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Status, Streaming};
+
+use crate::server::functional_units as proto;
+use crate::server::health_proto;
+use crate::trace::TraceContext;
+use crate::{Orientation, PlotError, PlotterFunction, PushOrPullError, Sheet, TurnError, Usage};
+
+type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Error of a call to a functional unit
+#[derive(Debug)]
+pub enum CallError<E> {
+    /// The unit refused the operation
+    Unit(E),
+    /// The call failed or the unit answered with an unknown result
+    Rpc(Status),
+}
+
+/// Details a unit attached to a failed call
+#[derive(PartialEq, Debug, Clone)]
+pub struct ErrorDetails {
+    /// Cause of the error in UPPER_SNAKE_CASE, e.g. `UNKNOWN_ORIENTATION`
+    pub reason: String,
+    pub metadata: HashMap<String, String>,
+}
+
+impl ErrorDetails {
+    pub fn from_status(status: &Status) -> Option<ErrorDetails> {
+        if status.details().is_empty() {
+            return None;
+        }
+        let info = proto::ErrorInfo::decode(status.details()).ok()?;
+        Some(ErrorDetails {
+            reason: info.reason,
+            metadata: info.metadata,
+        })
+    }
+}
+
+impl<E> From<Status> for CallError<E> {
+    fn from(status: Status) -> Self {
+        CallError::Rpc(status)
+    }
+}
+
+impl From<proto::PushOrPullResult> for Result<(), CallError<PushOrPullError>> {
+    fn from(res: proto::PushOrPullResult) -> Self {
+        match proto::push_or_pull_result::Code::from_i32(res.code) {
+            Some(proto::push_or_pull_result::Code::Ok) => Ok(()),
+            Some(proto::push_or_pull_result::Code::Empty) => {
+                Err(CallError::Unit(PushOrPullError::Empty))
+            }
+            Some(proto::push_or_pull_result::Code::Full) => {
+                Err(CallError::Unit(PushOrPullError::Full))
+            }
+            Some(proto::push_or_pull_result::Code::Jammed) => {
+                Err(CallError::Unit(PushOrPullError::Jammed))
+            }
+            Some(proto::push_or_pull_result::Code::Dropped) => {
+                Err(CallError::Unit(PushOrPullError::Dropped))
+            }
+            None => Err(CallError::Rpc(Status::unknown(format!(
+                "Unknown push or pull result {}",
+                res.code
+            )))),
+        }
+    }
+}
+
+impl From<proto::PushOrPullResult> for Result<Sheet, CallError<PushOrPullError>> {
+    /// Result of a push, which hands out the sheet on success
+    fn from(mut res: proto::PushOrPullResult) -> Self {
+        let sheet = res.sheet.take();
+        Result::<(), _>::from(res)?;
+        let sheet = sheet.ok_or_else(|| Status::unknown("No sheet handed out"))?;
+        Ok(Sheet::try_from(sheet).map_err(unknown_function)?)
+    }
+}
+
+impl From<proto::PlotResult> for Result<(), CallError<PlotError>> {
+    fn from(res: proto::PlotResult) -> Self {
+        match proto::plot_result::Code::from_i32(res.code) {
+            Some(proto::plot_result::Code::Ok) => Ok(()),
+            Some(proto::plot_result::Code::NoPaper) => Err(CallError::Unit(PlotError::NoPaper)),
+            Some(proto::plot_result::Code::UnsupportedFunction) => {
+                Err(CallError::Unit(PlotError::UnsupportedFunction))
+            }
+            Some(proto::plot_result::Code::PenFailure) => {
+                Err(CallError::Unit(PlotError::PenFailure))
+            }
+            None => Err(CallError::Rpc(Status::unknown(format!(
+                "Unknown plot result {}",
+                res.code
+            )))),
+        }
+    }
+}
+
+impl From<proto::TurnToResult> for Result<(), CallError<TurnError>> {
+    fn from(res: proto::TurnToResult) -> Self {
+        match proto::turn_to_result::Code::from_i32(res.code) {
+            Some(proto::turn_to_result::Code::Ok) => Ok(()),
+            Some(proto::turn_to_result::Code::Stalled) => Err(CallError::Unit(TurnError::Stalled)),
+            None => Err(CallError::Rpc(Status::unknown(format!(
+                "Unknown turn result {}",
+                res.code
+            )))),
+        }
+    }
+}
+
+impl TryFrom<proto::RemovedSheets> for Vec<Sheet> {
+    type Error = Status;
+
+    fn try_from(removed: proto::RemovedSheets) -> Result<Self, Self::Error> {
+        let mut sheets = Vec::with_capacity(removed.sheets.len());
+        for sheet in removed.sheets {
+            sheets.push(Sheet::try_from(sheet).map_err(unknown_function)?);
+        }
+        Ok(sheets)
+    }
+}
+
+fn orientation(value: i32) -> Option<Orientation> {
+    proto::Orientation::from_i32(value).map(Into::into)
+}
+
+fn function(value: i32) -> Option<PlotterFunction> {
+    proto::PlotterFunction::from_i32(value).map(Into::into)
+}
+
+fn unknown_function(value: i32) -> Status {
+    Status::unknown(format!("Unknown plotter function {}", value))
+}
+
+fn pull_request(sheet: &Sheet) -> proto::PullRequest {
+    proto::PullRequest {
+        sheet: Some(sheet.into()),
+    }
+}
+
+async fn connect<D>(dst: D) -> Result<Channel, tonic::transport::Error>
+where
+    D: TryInto<Endpoint>,
+    D::Error: Into<StdError>,
+{
+    Endpoint::new(dst)?.connect().await
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct PlotterStatus {
+    pub name: String,
+    pub has_paper: bool,
+    pub functions: Vec<PlotterFunction>,
+    pub sheet: Option<Sheet>,
+    pub usage: Usage,
+    /// Wear since the unit last left maintenance
+    pub wear: f64,
+    pub maintenance_due: bool,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ConveyorStatus {
+    pub name: String,
+    pub has_paper: bool,
+    pub orientation: Orientation,
+    pub sheet: Option<Sheet>,
+    pub usage: Usage,
+    /// Wear since the unit last left maintenance
+    pub wear: f64,
+    pub maintenance_due: bool,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct InputStackStatus {
+    pub name: String,
+    pub paper_count: u32,
+    /// Sheets put back on top of the stack, the last one is handed out next
+    pub returned: Vec<Sheet>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct OutputStackStatus {
+    pub name: String,
+    pub paper_count: u32,
+    pub sheets: Vec<Sheet>,
+}
+
+impl From<proto::Usage> for Usage {
+    fn from(usage: proto::Usage) -> Self {
+        Usage {
+            plots: usage.plots,
+            turns: usage.turns,
+            degrees_rotated: usage.degrees_rotated,
+            sheets_moved: usage.sheets_moved,
+            uptime: Duration::from_secs(usage.uptime_seconds),
+        }
+    }
+}
+
+impl TryFrom<proto::PlotterStatus> for PlotterStatus {
+    type Error = Status;
+
+    fn try_from(reply: proto::PlotterStatus) -> Result<Self, Self::Error> {
+        let mut functions = Vec::with_capacity(reply.functions.len());
+        for &f in &reply.functions {
+            functions.push(function(f).ok_or_else(|| unknown_function(f))?);
+        }
+        let sheet = match reply.sheet {
+            Some(sheet) => Some(Sheet::try_from(sheet).map_err(unknown_function)?),
+            None => None,
+        };
+        Ok(PlotterStatus {
+            name: reply.name,
+            has_paper: reply.has_paper,
+            functions,
+            sheet,
+            usage: reply.usage.map(Into::into).unwrap_or_default(),
+            wear: reply.wear,
+            maintenance_due: reply.maintenance_due,
+        })
+    }
+}
+
+impl TryFrom<proto::ConveyorStatus> for ConveyorStatus {
+    type Error = Status;
+
+    fn try_from(reply: proto::ConveyorStatus) -> Result<Self, Self::Error> {
+        let orientation = orientation(reply.orientation)
+            .ok_or_else(|| Status::unknown(format!("Unknown orientation {}", reply.orientation)))?;
+        let sheet = match reply.sheet {
+            Some(sheet) => Some(Sheet::try_from(sheet).map_err(unknown_function)?),
+            None => None,
+        };
+        Ok(ConveyorStatus {
+            name: reply.name,
+            has_paper: reply.has_paper,
+            orientation,
+            sheet,
+            usage: reply.usage.map(Into::into).unwrap_or_default(),
+            wear: reply.wear,
+            maintenance_due: reply.maintenance_due,
+        })
+    }
+}
+
+impl TryFrom<proto::InputStackStatus> for InputStackStatus {
+    type Error = Status;
+
+    fn try_from(reply: proto::InputStackStatus) -> Result<Self, Self::Error> {
+        let mut returned = Vec::with_capacity(reply.returned.len());
+        for sheet in reply.returned {
+            returned.push(Sheet::try_from(sheet).map_err(unknown_function)?);
+        }
+        Ok(InputStackStatus {
+            name: reply.name,
+            paper_count: reply.paper_count,
+            returned,
+        })
+    }
+}
+
+impl TryFrom<proto::OutputStackStatus> for OutputStackStatus {
+    type Error = Status;
+
+    fn try_from(reply: proto::OutputStackStatus) -> Result<Self, Self::Error> {
+        let mut sheets = Vec::with_capacity(reply.sheets.len());
+        for sheet in reply.sheets {
+            sheets.push(Sheet::try_from(sheet).map_err(unknown_function)?);
+        }
+        Ok(OutputStackStatus {
+            name: reply.name,
+            paper_count: reply.paper_count,
+            sheets,
+        })
+    }
+}
+
+/// Call that changed or tried to change the state of a unit
+#[derive(PartialEq, Debug, Clone)]
+pub struct HistoryEvent {
+    pub time: SystemTime,
+    /// Name of the RPC, e.g. `TurnTo`
+    pub method: String,
+    pub description: String,
+    /// Result code of the call, e.g. `Ok` or `Empty`
+    pub result: String,
+    /// Address the call came from, empty if unknown
+    pub peer: String,
+    /// Name the caller sent in its `caller` metadata, empty if none
+    pub caller: String,
+}
+
+impl From<proto::HistoryEvent> for HistoryEvent {
+    fn from(e: proto::HistoryEvent) -> Self {
+        HistoryEvent {
+            time: e
+                .time
+                .and_then(|t| SystemTime::try_from(t).ok())
+                .unwrap_or(UNIX_EPOCH),
+            method: e.method,
+            description: e.description,
+            result: e.result,
+            peer: e.peer,
+            caller: e.caller,
+        }
+    }
+}
+
+fn history_request(
+    since: Option<SystemTime>,
+    until: Option<SystemTime>,
+    limit: u32,
+) -> proto::GetHistoryRequest {
+    proto::GetHistoryRequest {
+        since: since.map(Into::into),
+        until: until.map(Into::into),
+        limit,
+    }
+}
+
+/// Metadata key of the caller's name, kept in the units' history
+const CALLER: &str = "caller";
+
+/// Metadata a client sends along with every call
+#[derive(Clone, Default)]
+struct CallContext {
+    trace: Option<TraceContext>,
+    caller: Option<AsciiMetadataValue>,
+}
+
+impl CallContext {
+    /// Request carrying the trace context and the caller's name, if any
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut req = Request::new(message);
+        if let Some(trace) = self.trace {
+            trace.inject(req.metadata_mut());
+        }
+        if let Some(caller) = &self.caller {
+            req.metadata_mut().insert(CALLER, caller.clone());
+        }
+        req
+    }
+}
+
+fn history(reply: proto::History) -> Vec<HistoryEvent> {
+    reply.events.into_iter().map(Into::into).collect()
+}
+
+/// Statuses of a watched unit. The stream ends after the first error.
+pub type StatusWatch<T> = mpsc::Receiver<Result<T, Status>>;
+
+fn watch<M, T>(mut stream: Streaming<M>) -> StatusWatch<T>
+where
+    M: Message + Default + Send + 'static,
+    T: TryFrom<M, Error = Status> + Send + 'static,
+{
+    let (mut tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        loop {
+            let status = match stream.message().await {
+                Ok(Some(reply)) => T::try_from(reply),
+                Ok(None) => break,
+                Err(e) => Err(e),
+            };
+            let failed = status.is_err();
+            if tx.send(status).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+#[derive(Clone)]
+pub struct PlotterClient {
+    inner: proto::plotter_client::PlotterClient<Channel>,
+    context: CallContext,
+}
+
+impl PlotterClient {
+    pub fn new(channel: Channel) -> PlotterClient {
+        PlotterClient {
+            inner: proto::plotter_client::PlotterClient::new(channel),
+            context: CallContext::default(),
+        }
+    }
+
+    pub async fn connect<D>(dst: D) -> Result<PlotterClient, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<StdError>,
+    {
+        Ok(PlotterClient::new(connect(dst).await?))
+    }
+
+    /// Sends the trace context along with every call, so the unit's spans
+    /// join the caller's trace
+    pub fn with_trace(mut self, trace: TraceContext) -> PlotterClient {
+        self.context.trace = Some(trace);
+        self
+    }
+
+    /// Names the caller in the unit's history. Names that are not visible
+    /// ASCII are left out.
+    pub fn with_caller(mut self, name: &str) -> PlotterClient {
+        self.context.caller = name.parse().ok();
+        self
+    }
+
+    pub async fn status(&mut self) -> Result<PlotterStatus, Status> {
+        self.inner
+            .status(self.context.request(()))
+            .await?
+            .into_inner()
+            .try_into()
+    }
+
+    /// Current status followed by every change, with an uptime of 0 as it
+    /// changes every second
+    pub async fn watch_status(&mut self) -> Result<StatusWatch<PlotterStatus>, Status> {
+        Ok(watch(
+            self.inner
+                .watch_status(self.context.request(()))
+                .await?
+                .into_inner(),
+        ))
+    }
+
+    pub async fn plot(&mut self, function: PlotterFunction) -> Result<(), CallError<PlotError>> {
+        let req = proto::PlotRequest {
+            function: function.into(),
+        };
+        self.inner
+            .plot(self.context.request(req))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Hands out the sheet held by the unit
+    pub async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>> {
+        self.inner
+            .push(self.context.request(()))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Takes over the sheet pushed by a neighbour
+    pub async fn pull(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        self.inner
+            .pull(self.context.request(pull_request(sheet)))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Puts a sheet on the unit without any delay or fault
+    pub async fn insert_paper(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        self.inner
+            .insert_paper(self.context.request(pull_request(sheet)))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Takes the sheet off the unit
+    pub async fn clear_paper(&mut self) -> Result<Option<Sheet>, Status> {
+        let sheets: Vec<Sheet> = self
+            .inner
+            .clear_paper(self.context.request(()))
+            .await?
+            .into_inner()
+            .try_into()?;
+        Ok(sheets.into_iter().next())
+    }
+
+    /// Takes the unit off the floor or puts it back
+    pub async fn set_maintenance(&mut self, enabled: bool) -> Result<(), Status> {
+        let req = proto::SetMaintenanceRequest { enabled };
+        self.inner
+            .set_maintenance(self.context.request(req))
+            .await?;
+        Ok(())
+    }
+
+    /// Puts the unit back into the state it started in
+    pub async fn reset(&mut self) -> Result<(), Status> {
+        self.inner.reset(self.context.request(())).await?;
+        Ok(())
+    }
+
+    /// Events of the unit from `since` up to `until`, only the latest `limit`
+    /// of them unless it is 0
+    pub async fn history(
+        &mut self,
+        since: Option<SystemTime>,
+        until: Option<SystemTime>,
+        limit: u32,
+    ) -> Result<Vec<HistoryEvent>, Status> {
+        let req = history_request(since, until, limit);
+        Ok(history(
+            self.inner
+                .get_history(self.context.request(req))
+                .await?
+                .into_inner(),
+        ))
+    }
+}
+
+#[derive(Clone)]
+pub struct ConveyorClient {
+    inner: proto::conveyor_client::ConveyorClient<Channel>,
+    context: CallContext,
+}
+
+impl ConveyorClient {
+    pub fn new(channel: Channel) -> ConveyorClient {
+        ConveyorClient {
+            inner: proto::conveyor_client::ConveyorClient::new(channel),
+            context: CallContext::default(),
+        }
+    }
+
+    pub async fn connect<D>(dst: D) -> Result<ConveyorClient, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<StdError>,
+    {
+        Ok(ConveyorClient::new(connect(dst).await?))
+    }
+
+    /// Sends the trace context along with every call, so the unit's spans
+    /// join the caller's trace
+    pub fn with_trace(mut self, trace: TraceContext) -> ConveyorClient {
+        self.context.trace = Some(trace);
+        self
+    }
+
+    /// Names the caller in the unit's history. Names that are not visible
+    /// ASCII are left out.
+    pub fn with_caller(mut self, name: &str) -> ConveyorClient {
+        self.context.caller = name.parse().ok();
+        self
+    }
+
+    pub async fn status(&mut self) -> Result<ConveyorStatus, Status> {
+        self.inner
+            .status(self.context.request(()))
+            .await?
+            .into_inner()
+            .try_into()
+    }
+
+    /// Current status followed by every change, with an uptime of 0 as it
+    /// changes every second
+    pub async fn watch_status(&mut self) -> Result<StatusWatch<ConveyorStatus>, Status> {
+        Ok(watch(
+            self.inner
+                .watch_status(self.context.request(()))
+                .await?
+                .into_inner(),
+        ))
+    }
+
+    pub async fn turn_to(&mut self, target: Orientation) -> Result<(), CallError<TurnError>> {
+        let req = proto::TurnToRequest {
+            target: (&target).into(),
+        };
+        self.inner
+            .turn_to(self.context.request(req))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Hands out the sheet held by the unit
+    pub async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>> {
+        self.inner
+            .push(self.context.request(()))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Takes over the sheet pushed by a neighbour
+    pub async fn pull(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        self.inner
+            .pull(self.context.request(pull_request(sheet)))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Puts a sheet on the unit without any delay or fault
+    pub async fn insert_paper(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        self.inner
+            .insert_paper(self.context.request(pull_request(sheet)))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Takes the sheet off the unit
+    pub async fn clear_paper(&mut self) -> Result<Option<Sheet>, Status> {
+        let sheets: Vec<Sheet> = self
+            .inner
+            .clear_paper(self.context.request(()))
+            .await?
+            .into_inner()
+            .try_into()?;
+        Ok(sheets.into_iter().next())
+    }
+
+    /// Takes the unit off the floor or puts it back
+    pub async fn set_maintenance(&mut self, enabled: bool) -> Result<(), Status> {
+        let req = proto::SetMaintenanceRequest { enabled };
+        self.inner
+            .set_maintenance(self.context.request(req))
+            .await?;
+        Ok(())
+    }
+
+    /// Puts the unit back into the state it started in
+    pub async fn reset(&mut self) -> Result<(), Status> {
+        self.inner.reset(self.context.request(())).await?;
+        Ok(())
+    }
+
+    /// Events of the unit from `since` up to `until`, only the latest `limit`
+    /// of them unless it is 0
+    pub async fn history(
+        &mut self,
+        since: Option<SystemTime>,
+        until: Option<SystemTime>,
+        limit: u32,
+    ) -> Result<Vec<HistoryEvent>, Status> {
+        let req = history_request(since, until, limit);
+        Ok(history(
+            self.inner
+                .get_history(self.context.request(req))
+                .await?
+                .into_inner(),
+        ))
+    }
+}
+
+#[derive(Clone)]
+pub struct InputStackClient {
+    inner: proto::input_stack_client::InputStackClient<Channel>,
+    context: CallContext,
+}
+
+impl InputStackClient {
+    pub fn new(channel: Channel) -> InputStackClient {
+        InputStackClient {
+            inner: proto::input_stack_client::InputStackClient::new(channel),
+            context: CallContext::default(),
+        }
+    }
+
+    pub async fn connect<D>(dst: D) -> Result<InputStackClient, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<StdError>,
+    {
+        Ok(InputStackClient::new(connect(dst).await?))
+    }
+
+    /// Sends the trace context along with every call, so the unit's spans
+    /// join the caller's trace
+    pub fn with_trace(mut self, trace: TraceContext) -> InputStackClient {
+        self.context.trace = Some(trace);
+        self
+    }
+
+    /// Names the caller in the unit's history. Names that are not visible
+    /// ASCII are left out.
+    pub fn with_caller(mut self, name: &str) -> InputStackClient {
+        self.context.caller = name.parse().ok();
+        self
+    }
+
+    pub async fn status(&mut self) -> Result<InputStackStatus, Status> {
+        self.inner
+            .status(self.context.request(()))
+            .await?
+            .into_inner()
+            .try_into()
+    }
+
+    /// Current status followed by every change
+    pub async fn watch_status(&mut self) -> Result<StatusWatch<InputStackStatus>, Status> {
+        Ok(watch(
+            self.inner
+                .watch_status(self.context.request(()))
+                .await?
+                .into_inner(),
+        ))
+    }
+
+    /// Hands out the sheet held by the unit
+    pub async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>> {
+        self.inner
+            .push(self.context.request(()))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Puts a sheet back on top of the stack
+    pub async fn pull(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        self.inner
+            .pull(self.context.request(pull_request(sheet)))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Puts a sheet back on top of the stack without any delay or fault
+    pub async fn insert_paper(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        self.inner
+            .insert_paper(self.context.request(pull_request(sheet)))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Replaces all sheets with `count` blank ones
+    pub async fn set_paper_count(&mut self, count: u32) -> Result<(), Status> {
+        let req = proto::SetPaperCountRequest { paper_count: count };
+        self.inner
+            .set_paper_count(self.context.request(req))
+            .await?;
+        Ok(())
+    }
+
+    /// Sets the paper count back to the one the stack started with
+    pub async fn refill(&mut self) -> Result<(), Status> {
+        self.inner.refill(self.context.request(())).await?;
+        Ok(())
+    }
+
+    /// Takes the unit off the floor or puts it back
+    pub async fn set_maintenance(&mut self, enabled: bool) -> Result<(), Status> {
+        let req = proto::SetMaintenanceRequest { enabled };
+        self.inner
+            .set_maintenance(self.context.request(req))
+            .await?;
+        Ok(())
+    }
+
+    /// Puts the unit back into the state it started in
+    pub async fn reset(&mut self) -> Result<(), Status> {
+        self.inner.reset(self.context.request(())).await?;
+        Ok(())
+    }
+
+    /// Events of the unit from `since` up to `until`, only the latest `limit`
+    /// of them unless it is 0
+    pub async fn history(
+        &mut self,
+        since: Option<SystemTime>,
+        until: Option<SystemTime>,
+        limit: u32,
+    ) -> Result<Vec<HistoryEvent>, Status> {
+        let req = history_request(since, until, limit);
+        Ok(history(
+            self.inner
+                .get_history(self.context.request(req))
+                .await?
+                .into_inner(),
+        ))
+    }
+}
+
+#[derive(Clone)]
+pub struct OutputStackClient {
+    inner: proto::output_stack_client::OutputStackClient<Channel>,
+    context: CallContext,
+}
+
+impl OutputStackClient {
+    pub fn new(channel: Channel) -> OutputStackClient {
+        OutputStackClient {
+            inner: proto::output_stack_client::OutputStackClient::new(channel),
+            context: CallContext::default(),
+        }
+    }
+
+    pub async fn connect<D>(dst: D) -> Result<OutputStackClient, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<StdError>,
+    {
+        Ok(OutputStackClient::new(connect(dst).await?))
+    }
+
+    /// Sends the trace context along with every call, so the unit's spans
+    /// join the caller's trace
+    pub fn with_trace(mut self, trace: TraceContext) -> OutputStackClient {
+        self.context.trace = Some(trace);
+        self
+    }
+
+    /// Names the caller in the unit's history. Names that are not visible
+    /// ASCII are left out.
+    pub fn with_caller(mut self, name: &str) -> OutputStackClient {
+        self.context.caller = name.parse().ok();
+        self
+    }
+
+    pub async fn status(&mut self) -> Result<OutputStackStatus, Status> {
+        self.inner
+            .status(self.context.request(()))
+            .await?
+            .into_inner()
+            .try_into()
+    }
+
+    /// Current status followed by every change
+    pub async fn watch_status(&mut self) -> Result<StatusWatch<OutputStackStatus>, Status> {
+        Ok(watch(
+            self.inner
+                .watch_status(self.context.request(()))
+                .await?
+                .into_inner(),
+        ))
+    }
+
+    /// Takes over the sheet pushed by a neighbour
+    pub async fn pull(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        self.inner
+            .pull(self.context.request(pull_request(sheet)))
+            .await?
+            .into_inner()
+            .into()
+    }
+
+    /// Takes all finished sheets off the stack
+    pub async fn clear(&mut self) -> Result<Vec<Sheet>, Status> {
+        self.inner
+            .clear(self.context.request(()))
+            .await?
+            .into_inner()
+            .try_into()
+    }
+
+    /// Takes the unit off the floor or puts it back
+    pub async fn set_maintenance(&mut self, enabled: bool) -> Result<(), Status> {
+        let req = proto::SetMaintenanceRequest { enabled };
+        self.inner
+            .set_maintenance(self.context.request(req))
+            .await?;
+        Ok(())
+    }
+
+    /// Puts the unit back into the state it started in
+    pub async fn reset(&mut self) -> Result<(), Status> {
+        self.inner.reset(self.context.request(())).await?;
+        Ok(())
+    }
+
+    /// Events of the unit from `since` up to `until`, only the latest `limit`
+    /// of them unless it is 0
+    pub async fn history(
+        &mut self,
+        since: Option<SystemTime>,
+        until: Option<SystemTime>,
+        limit: u32,
+    ) -> Result<Vec<HistoryEvent>, Status> {
+        let req = history_request(since, until, limit);
+        Ok(history(
+            self.inner
+                .get_history(self.context.request(req))
+                .await?
+                .into_inner(),
+        ))
+    }
+}
+
+/// Client for the gRPC health service every unit serves next to its own
+#[derive(Clone)]
+pub struct HealthClient {
+    inner: health_proto::health_client::HealthClient<Channel>,
+}
+
+impl HealthClient {
+    pub fn new(channel: Channel) -> HealthClient {
+        HealthClient {
+            inner: health_proto::health_client::HealthClient::new(channel),
+        }
+    }
+
+    pub async fn connect<D>(dst: D) -> Result<HealthClient, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<StdError>,
+    {
+        Ok(HealthClient::new(connect(dst).await?))
+    }
+
+    /// Whether `service` is serving, the empty name checks the whole server
+    pub async fn check(&mut self, service: &str) -> Result<bool, Status> {
+        let req = health_proto::HealthCheckRequest {
+            service: service.to_owned(),
+        };
+        let status = self.inner.check(req).await?.into_inner().status;
+        Ok(status == health_proto::health_check_response::ServingStatus::Serving as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_or_pull(code: proto::push_or_pull_result::Code) -> proto::PushOrPullResult {
+        proto::PushOrPullResult {
+            code: code.into(),
+            sheet: None,
+            error: None,
+        }
+    }
+
+    fn plot(code: proto::plot_result::Code) -> proto::PlotResult {
+        proto::PlotResult {
+            code: code.into(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn call_context() {
+        let trace = TraceContext::new_root();
+        let context = CallContext {
+            trace: Some(trace),
+            caller: "orchestrator order #3".parse().ok(),
+        };
+        let req = context.request(());
+        assert_eq!(Some(trace), TraceContext::extract(req.metadata()));
+        let caller = req.metadata().get(CALLER).unwrap().to_str().unwrap();
+        assert_eq!("orchestrator order #3", caller);
+        assert!(CallContext::default().request(()).metadata().is_empty());
+    }
+
+    #[test]
+    fn push_or_pull_result() {
+        let res: Result<(), CallError<PushOrPullError>> =
+            push_or_pull(proto::push_or_pull_result::Code::Ok).into();
+        assert!(res.is_ok());
+
+        let res: Result<(), CallError<PushOrPullError>> =
+            push_or_pull(proto::push_or_pull_result::Code::Empty).into();
+        match res {
+            Err(CallError::Unit(PushOrPullError::Empty)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let res: Result<(), CallError<PushOrPullError>> =
+            push_or_pull(proto::push_or_pull_result::Code::Full).into();
+        match res {
+            Err(CallError::Unit(PushOrPullError::Full)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let res: Result<(), CallError<PushOrPullError>> =
+            push_or_pull(proto::push_or_pull_result::Code::Dropped).into();
+        match res {
+            Err(CallError::Unit(PushOrPullError::Dropped)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn turn_to_result() {
+        let sent: proto::TurnToResult = Result::<(), _>::Err(TurnError::Stalled).into();
+        assert_eq!("STALLED", sent.error.as_ref().unwrap().reason);
+        let received: Result<(), CallError<TurnError>> = sent.into();
+        match received {
+            Err(CallError::Unit(TurnError::Stalled)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn push_or_pull_result_roundtrip() {
+        let sent: proto::PushOrPullResult = Result::<(), _>::Err(PushOrPullError::Full).into();
+        let received: Result<(), CallError<PushOrPullError>> = sent.into();
+        match received {
+            Err(CallError::Unit(PushOrPullError::Full)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_push_or_pull_result() {
+        let res: Result<(), CallError<PushOrPullError>> = proto::PushOrPullResult {
+            code: 42,
+            sheet: None,
+            error: None,
+        }
+        .into();
+        match res {
+            Err(CallError::Rpc(status)) => assert_eq!(tonic::Code::Unknown, status.code()),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pushed_sheet() {
+        let mut sheet = Sheet::new("Main-1");
+        sheet.stamp(PlotterFunction::DrawBlue);
+        let sent: proto::PushOrPullResult = Ok(sheet.clone()).into();
+        let received: Result<Sheet, CallError<PushOrPullError>> = sent.into();
+        assert_eq!(sheet, received.unwrap());
+
+        let received: Result<Sheet, CallError<PushOrPullError>> =
+            push_or_pull(proto::push_or_pull_result::Code::Empty).into();
+        match received {
+            Err(CallError::Unit(PushOrPullError::Empty)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn push_without_sheet() {
+        let res: Result<Sheet, CallError<PushOrPullError>> =
+            push_or_pull(proto::push_or_pull_result::Code::Ok).into();
+        match res {
+            Err(CallError::Rpc(status)) => assert_eq!(tonic::Code::Unknown, status.code()),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sheet_with_unknown_function() {
+        let sheet = proto::Sheet {
+            id: "Main-1".to_owned(),
+            functions: vec![PlotterFunction::DrawRed.into(), 42],
+        };
+        assert_eq!(Err(42), Sheet::try_from(sheet));
+    }
+
+    #[test]
+    fn plot_result() {
+        let res: Result<(), CallError<PlotError>> = plot(proto::plot_result::Code::Ok).into();
+        assert!(res.is_ok());
+
+        let res: Result<(), CallError<PlotError>> = plot(proto::plot_result::Code::NoPaper).into();
+        match res {
+            Err(CallError::Unit(PlotError::NoPaper)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let res: Result<(), CallError<PlotError>> =
+            plot(proto::plot_result::Code::UnsupportedFunction).into();
+        match res {
+            Err(CallError::Unit(PlotError::UnsupportedFunction)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn failed_result_has_reason() {
+        let res: proto::PushOrPullResult = Result::<(), _>::Err(PushOrPullError::Full).into();
+        assert_eq!("UNIT_FULL", res.error.unwrap().reason);
+
+        let res: proto::PlotResult = Err(PlotError::UnsupportedFunction).into();
+        assert_eq!("UNSUPPORTED_FUNCTION", res.error.unwrap().reason);
+
+        let res: proto::PlotResult = Ok(()).into();
+        assert_eq!(None, res.error);
+    }
+
+    #[test]
+    fn error_details() {
+        let status = crate::server::status_with_info(
+            tonic::Code::InvalidArgument,
+            "Unknown orientation",
+            "UNKNOWN_ORIENTATION",
+            &[("value", "42".to_owned())],
+        );
+        let details = ErrorDetails::from_status(&status).unwrap();
+        assert_eq!("UNKNOWN_ORIENTATION", details.reason);
+        assert_eq!(Some(&"42".to_owned()), details.metadata.get("value"));
+
+        assert_eq!(
+            None,
+            ErrorDetails::from_status(&Status::internal("No details"))
+        );
+    }
+
+    #[test]
+    fn conveyor_status() {
+        let reply = proto::ConveyorStatus {
+            name: "conv1".to_owned(),
+            has_paper: true,
+            orientation: proto::Orientation::South.into(),
+            sheet: Some((&Sheet::new("Main-1")).into()),
+            usage: Some(proto::Usage {
+                turns: 3,
+                degrees_rotated: 360,
+                uptime_seconds: 60,
+                ..proto::Usage::default()
+            }),
+            wear: 1.25,
+            maintenance_due: true,
+        };
+        assert_eq!(
+            Ok(ConveyorStatus {
+                name: "conv1".to_owned(),
+                has_paper: true,
+                orientation: Orientation::South,
+                sheet: Some(Sheet::new("Main-1")),
+                usage: Usage {
+                    turns: 3,
+                    degrees_rotated: 360,
+                    uptime: Duration::from_secs(60),
+                    ..Usage::default()
+                },
+                wear: 1.25,
+                maintenance_due: true,
+            }),
+            ConveyorStatus::try_from(reply.clone()).map_err(|e| e.code())
+        );
+
+        let reply = proto::ConveyorStatus {
+            orientation: 42,
+            ..reply
+        };
+        assert_eq!(
+            Err(tonic::Code::Unknown),
+            ConveyorStatus::try_from(reply).map_err(|e| e.code())
+        );
+    }
+
+    #[test]
+    fn orientation_from_i32() {
+        assert_eq!(
+            Some(Orientation::West),
+            orientation(proto::Orientation::West.into())
+        );
+        assert_eq!(None, orientation(42));
+    }
+
+    #[test]
+    fn function_from_i32() {
+        for &f in PlotterFunction::all().iter() {
+            assert_eq!(Some(f), function(f.into()));
+        }
+        assert_eq!(None, function(42));
+    }
+}
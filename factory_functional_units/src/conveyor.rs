@@ -1,9 +1,18 @@
-use crate::{Orientation, PushOrPullError};
+use crate::{Orientation, PushOrPullError, Sheet, Usage};
+
+#[derive(PartialEq, Debug)]
+pub enum TurnError {
+    /// The conveyor did not turn
+    Stalled,
+}
 
 pub struct Conveyor {
     name: String,
     current_orientation: Orientation,
-    has_paper: bool,
+    sheet: Option<Sheet>,
+    turns: u64,
+    degrees_rotated: u64,
+    sheets_moved: u64,
 }
 
 impl Conveyor {
@@ -11,7 +20,10 @@ impl Conveyor {
         Conveyor {
             name: String::from(name),
             current_orientation: Orientation::East,
-            has_paper: false,
+            sheet: None,
+            turns: 0,
+            degrees_rotated: 0,
+            sheets_moved: 0,
         }
     }
 
@@ -24,32 +36,61 @@ impl Conveyor {
     }
 
     pub fn has_paper(&self) -> bool {
-        self.has_paper
+        self.sheet.is_some()
     }
 
-    pub fn turn_to(&mut self, new_orientation: Orientation) {
-        if self.current_orientation != new_orientation {
-            self.current_orientation = new_orientation;
-        }
+    pub fn sheet(&self) -> Option<&Sheet> {
+        self.sheet.as_ref()
     }
 
-    pub fn push(&mut self) -> Result<(), PushOrPullError> {
-        if !self.has_paper {
-            Err(PushOrPullError::Empty)
-        } else {
-            self.has_paper = false;
-            Ok(())
+    /// Turns in the shorter direction and returns the number of 90° steps
+    /// taken, 0 if the conveyor already faces `new_orientation`
+    pub fn turn_to(&mut self, new_orientation: Orientation) -> u32 {
+        let steps = self.current_orientation.steps_to(new_orientation);
+        self.current_orientation = new_orientation;
+        if steps > 0 {
+            self.turns += 1;
+            self.degrees_rotated += u64::from(steps) * 90;
         }
+        steps
+    }
+
+    pub fn push(&mut self) -> Result<Sheet, PushOrPullError> {
+        let sheet = self.sheet.take().ok_or(PushOrPullError::Empty)?;
+        self.sheets_moved += 1;
+        Ok(sheet)
     }
 
-    pub fn pull(&mut self) -> Result<(), PushOrPullError> {
-        if self.has_paper {
+    pub fn pull(&mut self, sheet: Sheet) -> Result<(), PushOrPullError> {
+        if self.sheet.is_some() {
             Err(PushOrPullError::Full)
         } else {
-            self.has_paper = true;
+            self.sheet = Some(sheet);
             Ok(())
         }
     }
+
+    /// Takes the sheet off the unit
+    pub fn clear(&mut self) -> Option<Sheet> {
+        self.sheet.take()
+    }
+
+    /// Puts the conveyor back into the state it started in, keeping its
+    /// usage
+    pub fn reset(&mut self) -> Option<Sheet> {
+        self.current_orientation = Orientation::East;
+        self.clear()
+    }
+
+    /// Turns and sheets moved over the conveyor's lifetime, without uptime
+    pub fn usage(&self) -> Usage {
+        Usage {
+            turns: self.turns,
+            degrees_rotated: self.degrees_rotated,
+            sheets_moved: self.sheets_moved,
+            ..Usage::default()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -62,38 +103,70 @@ mod tests {
         let conv = Conveyor::new(name);
         assert_eq!(name, conv.name());
         assert_eq!(&Orientation::East, conv.orientation());
+        assert_eq!(None, conv.sheet());
     }
 
     #[test]
     fn turn_to() {
         let mut conv = Conveyor::new("Left");
-        conv.turn_to(Orientation::North);
+        assert_eq!(1, conv.turn_to(Orientation::North));
         assert_eq!(&Orientation::North, conv.orientation());
 
-        conv.turn_to(Orientation::East);
+        assert_eq!(1, conv.turn_to(Orientation::East));
+        assert_eq!(&Orientation::East, conv.orientation());
+
+        assert_eq!(0, conv.turn_to(Orientation::East));
         assert_eq!(&Orientation::East, conv.orientation());
 
-        conv.turn_to(Orientation::South);
+        assert_eq!(2, conv.turn_to(Orientation::West));
+        assert_eq!(&Orientation::West, conv.orientation());
+
+        assert_eq!(1, conv.turn_to(Orientation::South));
         assert_eq!(&Orientation::South, conv.orientation());
+    }
 
+    #[test]
+    fn usage() {
+        let mut conv = Conveyor::new("Left");
         conv.turn_to(Orientation::West);
-        assert_eq!(&Orientation::West, conv.orientation());
+        conv.turn_to(Orientation::West);
+        conv.turn_to(Orientation::North);
+        assert_eq!(Ok(()), conv.pull(Sheet::new("Main-1")));
+        assert!(conv.push().is_ok());
+        conv.reset();
+
+        let usage = conv.usage();
+        assert_eq!(2, usage.turns);
+        assert_eq!(270, usage.degrees_rotated);
+        assert_eq!(1, usage.sheets_moved);
     }
 
     #[test]
     fn pull_and_push() -> Result<(), PushOrPullError> {
         let mut conv = Conveyor::new("Left");
-        conv.pull()?;
-        assert_eq!(true, conv.has_paper);
+        conv.pull(Sheet::new("Main-1"))?;
+        assert!(conv.has_paper());
+        assert_eq!(Some(&Sheet::new("Main-1")), conv.sheet());
         assert_eq!(&Orientation::East, conv.orientation());
 
-        conv.push()?;
-        assert_eq!(false, conv.has_paper);
+        assert_eq!(Sheet::new("Main-1"), conv.push()?);
+        assert!(!conv.has_paper());
         assert_eq!(&Orientation::East, conv.orientation());
 
         Ok(())
     }
 
+    #[test]
+    fn reset() {
+        let mut conv = Conveyor::new("Left");
+        conv.turn_to(Orientation::North);
+        assert_eq!(Ok(()), conv.pull(Sheet::new("Main-1")));
+
+        assert_eq!(Some(Sheet::new("Main-1")), conv.reset());
+        assert_eq!(&Orientation::East, conv.orientation());
+        assert!(!conv.has_paper());
+    }
+
     #[test]
     fn empty_push() {
         let mut conv = Conveyor::new("Left");
@@ -103,8 +176,9 @@ mod tests {
     #[test]
     fn full_pull() {
         let mut conv = Conveyor::new("Left");
-        assert_eq!(Ok(()), conv.pull());
+        assert_eq!(Ok(()), conv.pull(Sheet::new("Main-1")));
 
-        assert_eq!(Err(PushOrPullError::Full), conv.pull());
+        assert_eq!(Err(PushOrPullError::Full), conv.pull(Sheet::new("Main-2")));
+        assert_eq!(Some(&Sheet::new("Main-1")), conv.sheet());
     }
 }
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{PushOrPullError, Sheet};
+
+/// Failure of the real line a unit can simulate
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub enum Fault {
+    /// A plotter's pen fails while plotting
+    PenFailure,
+    /// A sheet gets stuck on a push or pull and stays where it was
+    Jam,
+    /// A conveyor does not turn
+    Stall,
+    /// A pushed sheet falls off the line and is lost
+    DroppedSheet,
+}
+
+const FAULTS: [Fault; 4] = [
+    Fault::PenFailure,
+    Fault::Jam,
+    Fault::Stall,
+    Fault::DroppedSheet,
+];
+
+/// When a fault strikes, by chance or on given calls
+#[derive(PartialEq, Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FaultModel {
+    /// Chance of every call to fail, from 0 to 1
+    probability: f64,
+    /// Calls that fail for sure, counting the calls the fault can strike
+    /// from 1
+    at_calls: Vec<u64>,
+}
+
+impl FaultModel {
+    fn validate(&self) -> Result<(), String> {
+        if self.probability >= 0.0 && self.probability <= 1.0 {
+            Ok(())
+        } else {
+            Err(format!(
+                "probability must be between 0 and 1, got {}",
+                self.probability
+            ))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FaultsError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid { fault: Fault, reason: String },
+}
+
+impl Display for FaultsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FaultsError::Io(e) => write!(f, "Could not read faults: {}", e),
+            FaultsError::Parse(e) => write!(f, "Could not parse faults: {}", e),
+            FaultsError::Invalid { fault, reason } => {
+                write!(f, "Invalid model of {:?}: {}", fault, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FaultsError {}
+
+impl From<std::io::Error> for FaultsError {
+    fn from(e: std::io::Error) -> Self {
+        FaultsError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for FaultsError {
+    fn from(e: toml::de::Error) -> Self {
+        FaultsError::Parse(e)
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct FaultsFile {
+    pen_failure: FaultModel,
+    jam: FaultModel,
+    stall: FaultModel,
+    dropped_sheet: FaultModel,
+}
+
+impl FaultsFile {
+    fn model(&self, fault: Fault) -> &FaultModel {
+        match fault {
+            Fault::PenFailure => &self.pen_failure,
+            Fault::Jam => &self.jam,
+            Fault::Stall => &self.stall,
+            Fault::DroppedSheet => &self.dropped_sheet,
+        }
+    }
+}
+
+/// Injects faults into the calls of a unit.
+///
+/// Faults only fail the call they strike, the unit stays operational.
+pub struct Faults {
+    models: FaultsFile,
+    rng: Mutex<StdRng>,
+    /// Calls each fault could have struck so far
+    calls: Mutex<HashMap<Fault, u64>>,
+}
+
+impl Faults {
+    /// Never fails a call
+    pub fn none() -> Faults {
+        Faults::new(FaultsFile::default())
+    }
+
+    fn new(models: FaultsFile) -> Faults {
+        Faults {
+            models,
+            rng: Mutex::new(StdRng::from_entropy()),
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Faults, FaultsError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Strikes the same calls on every run
+    pub fn seeded(mut self, seed: u64) -> Faults {
+        self.rng = Mutex::new(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// Decides whether the fault strikes the current call
+    pub fn strikes(&self, fault: Fault) -> bool {
+        let model = self.models.model(fault);
+        let call = {
+            let mut calls = self.calls.lock().unwrap_or_else(PoisonError::into_inner);
+            let call = calls.entry(fault).or_insert(0);
+            *call += 1;
+            *call
+        };
+        let strikes = model.at_calls.contains(&call)
+            || (model.probability > 0.0
+                && self
+                    .rng
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .gen_bool(model.probability));
+        if strikes {
+            warn!(fault = ?fault, call = call, "Injecting fault");
+        }
+        strikes
+    }
+
+    /// Push that may jam or drop the sheet
+    pub(crate) fn push<F>(&self, push: F) -> Result<Sheet, PushOrPullError>
+    where
+        F: FnOnce() -> Result<Sheet, PushOrPullError>,
+    {
+        if self.strikes(Fault::Jam) {
+            return Err(PushOrPullError::Jammed);
+        }
+        let sheet = push()?;
+        if self.strikes(Fault::DroppedSheet) {
+            warn!(sheet = sheet.id(), "Dropped sheet");
+            return Err(PushOrPullError::Dropped);
+        }
+        Ok(sheet)
+    }
+
+    /// Pull that may jam, leaving the sheet with the pushing unit
+    pub(crate) fn pull<F>(&self, pull: F) -> Result<(), PushOrPullError>
+    where
+        F: FnOnce() -> Result<(), PushOrPullError>,
+    {
+        if self.strikes(Fault::Jam) {
+            return Err(PushOrPullError::Jammed);
+        }
+        pull()
+    }
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Faults::none()
+    }
+}
+
+impl FromStr for Faults {
+    type Err = FaultsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let models: FaultsFile = toml::from_str(s)?;
+        for &fault in &FAULTS {
+            models
+                .model(fault)
+                .validate()
+                .map_err(|reason| FaultsError::Invalid { fault, reason })?;
+        }
+        Ok(Faults::new(models))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedule() {
+        let faults: Faults = "[jam]\nat_calls = [2, 3]".parse().unwrap();
+        let strikes: Vec<_> = (0..4).map(|_| faults.strikes(Fault::Jam)).collect();
+        assert_eq!(vec![false, true, true, false], strikes);
+        // Every fault counts its own calls
+        assert!(!faults.strikes(Fault::Stall));
+    }
+
+    #[test]
+    fn probability() {
+        let faults: Faults = "[stall]\nprobability = 1.0\n[pen_failure]\nprobability = 0.0"
+            .parse()
+            .unwrap();
+        assert!(faults.strikes(Fault::Stall));
+        assert!(!faults.strikes(Fault::PenFailure));
+
+        let seeded = || {
+            "[stall]\nprobability = 0.5"
+                .parse::<Faults>()
+                .unwrap()
+                .seeded(7)
+        };
+        let (first, second) = (seeded(), seeded());
+        for _ in 0..20 {
+            assert_eq!(first.strikes(Fault::Stall), second.strikes(Fault::Stall));
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        match "[jam]\nprobability = 1.5".parse::<Faults>() {
+            Err(FaultsError::Invalid { fault, .. }) => assert_eq!(Fault::Jam, fault),
+            other => panic!("Unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn dropped_sheet() {
+        let faults: Faults = "[dropped_sheet]\nat_calls = [1]".parse().unwrap();
+        assert_eq!(
+            Err(PushOrPullError::Dropped),
+            faults.push(|| Ok(Sheet::new("Main-1")))
+        );
+        assert_eq!(
+            Ok(Sheet::new("Main-2")),
+            faults.push(|| Ok(Sheet::new("Main-2")))
+        );
+    }
+
+    #[test]
+    fn example_faults() {
+        Faults::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/faults.toml")).unwrap();
+    }
+}
@@ -0,0 +1,426 @@
+use std::fmt::{Display, Formatter};
+
+use tonic::Status;
+
+use crate::{
+    CallError, ConveyorClient, InputStackClient, OutputStackClient, PlotterClient, PushOrPullError,
+    Sheet,
+};
+
+/// Unit a sheet can be pushed out of
+#[tonic::async_trait]
+pub trait SheetSender: Send {
+    async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>>;
+
+    /// Takes back a sheet the unit pushed before. Goes around the faults,
+    /// delays and usage of a pull, so a rollback neither fails by chance nor
+    /// wears the unit.
+    async fn take_back(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>>;
+
+    /// Whether the unit currently holds the sheet, ready to push it
+    async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status>;
+}
+
+/// Unit a sheet can be pulled into
+#[tonic::async_trait]
+pub trait SheetReceiver: Send {
+    async fn pull(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>>;
+
+    /// Whether the unit currently holds the sheet
+    async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status>;
+}
+
+#[derive(Debug)]
+pub enum HandoverError {
+    /// The sender did not hand out a sheet, nothing moved
+    Push(CallError<PushOrPullError>),
+    /// The receiver refused the sheet, it is back at the sender
+    Pull(CallError<PushOrPullError>),
+    /// Neither the receiver nor the sender holds the sheet anymore. The
+    /// returned sheet is the only record of it.
+    Stranded {
+        sheet: Sheet,
+        take_back: CallError<PushOrPullError>,
+    },
+    /// A resumed handover could not place the sheet with the receiver.
+    /// Neither unit holds it, the returned sheet is the only record of it.
+    Undelivered {
+        sheet: Sheet,
+        pull: CallError<PushOrPullError>,
+    },
+}
+
+impl Display for HandoverError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandoverError::Push(e) => write!(f, "Push failed: {:?}", e),
+            HandoverError::Pull(e) => write!(f, "Pull failed, sheet returned to sender: {:?}", e),
+            HandoverError::Stranded { sheet, take_back } => write!(
+                f,
+                "Sheet {} is stranded, the receiver refused it and taking it back failed: {:?}",
+                sheet.id(),
+                take_back
+            ),
+            HandoverError::Undelivered { sheet, pull } => write!(
+                f,
+                "Sheet {} is stranded, neither unit holds it and the receiver refused it: {:?}",
+                sheet.id(),
+                pull
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HandoverError {}
+
+/// Moves a sheet from `sender` to the adjacent `receiver`.
+///
+/// Either the receiver ends up with the sheet or it is back at the sender.
+/// If the pull fails without a clear answer, the receiver is asked whether
+/// the sheet arrived before the handover is rolled back, so a sheet is never
+/// held by both units.
+pub async fn handover(
+    sender: &mut dyn SheetSender,
+    receiver: &mut dyn SheetReceiver,
+) -> Result<Sheet, HandoverError> {
+    let sheet = sender.push().await.map_err(HandoverError::Push)?;
+    let pull = match receiver.pull(&sheet).await {
+        Ok(_) => return Ok(sheet),
+        Err(CallError::Rpc(status)) => {
+            if let Ok(true) = receiver.holds(&sheet).await {
+                return Ok(sheet);
+            }
+            CallError::Rpc(status)
+        }
+        Err(e) => e,
+    };
+    match sender.take_back(&sheet).await {
+        Ok(_) => Err(HandoverError::Pull(pull)),
+        Err(take_back) => Err(HandoverError::Stranded { sheet, take_back }),
+    }
+}
+
+/// Finishes a handover of `sheet` that was interrupted after the sender
+/// pushed it, e.g. as the caller crashed. The sheet ends up with the
+/// receiver: it stays there if it arrived, is handed over anew if it is
+/// back at the sender, and is pulled in from the caller's record of it if
+/// neither unit holds it.
+pub async fn resume_handover(
+    sender: &mut dyn SheetSender,
+    receiver: &mut dyn SheetReceiver,
+    sheet: &Sheet,
+) -> Result<Sheet, HandoverError> {
+    let undelivered = |pull| HandoverError::Undelivered {
+        sheet: sheet.clone(),
+        pull,
+    };
+    if receiver
+        .holds(sheet)
+        .await
+        .map_err(|e| undelivered(CallError::Rpc(e)))?
+    {
+        return Ok(sheet.clone());
+    }
+    if sender
+        .holds(sheet)
+        .await
+        .map_err(|e| undelivered(CallError::Rpc(e)))?
+    {
+        return handover(sender, receiver).await;
+    }
+    receiver.pull(sheet).await.map_err(undelivered)?;
+    Ok(sheet.clone())
+}
+
+#[tonic::async_trait]
+impl SheetSender for PlotterClient {
+    async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>> {
+        PlotterClient::push(self).await
+    }
+
+    async fn take_back(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        PlotterClient::insert_paper(self, sheet).await
+    }
+
+    async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+        Ok(self.status().await?.sheet.as_ref().map(Sheet::id) == Some(sheet.id()))
+    }
+}
+
+#[tonic::async_trait]
+impl SheetReceiver for PlotterClient {
+    async fn pull(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        PlotterClient::pull(self, sheet).await
+    }
+
+    async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+        Ok(self.status().await?.sheet.as_ref().map(Sheet::id) == Some(sheet.id()))
+    }
+}
+
+#[tonic::async_trait]
+impl SheetSender for ConveyorClient {
+    async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>> {
+        ConveyorClient::push(self).await
+    }
+
+    async fn take_back(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        ConveyorClient::insert_paper(self, sheet).await
+    }
+
+    async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+        Ok(self.status().await?.sheet.as_ref().map(Sheet::id) == Some(sheet.id()))
+    }
+}
+
+#[tonic::async_trait]
+impl SheetReceiver for ConveyorClient {
+    async fn pull(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        ConveyorClient::pull(self, sheet).await
+    }
+
+    async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+        Ok(self.status().await?.sheet.as_ref().map(Sheet::id) == Some(sheet.id()))
+    }
+}
+
+#[tonic::async_trait]
+impl SheetSender for InputStackClient {
+    async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>> {
+        InputStackClient::push(self).await
+    }
+
+    async fn take_back(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        InputStackClient::insert_paper(self, sheet).await
+    }
+
+    /// Stacks make up blank sheets as they push them, they only hold the
+    /// sheets they took back
+    async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+        Ok(self
+            .status()
+            .await?
+            .returned
+            .iter()
+            .any(|s| s.id() == sheet.id()))
+    }
+}
+
+#[tonic::async_trait]
+impl SheetReceiver for OutputStackClient {
+    async fn pull(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        OutputStackClient::pull(self, sheet).await
+    }
+
+    async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+        Ok(self
+            .status()
+            .await?
+            .sheets
+            .iter()
+            .any(|s| s.id() == sheet.id()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InputStack;
+
+    /// Unit holding at most one sheet, optionally losing the answer to a
+    /// pull or refusing to take sheets back
+    #[derive(Default)]
+    struct Slot {
+        sheet: Option<Sheet>,
+        lose_pull_reply: bool,
+        refuse_take_back: bool,
+    }
+
+    impl Slot {
+        fn with(id: &str) -> Slot {
+            Slot {
+                sheet: Some(Sheet::new(id)),
+                ..Slot::default()
+            }
+        }
+
+        fn put(&mut self, sheet: &Sheet) -> Result<(), PushOrPullError> {
+            if self.sheet.is_some() {
+                return Err(PushOrPullError::Full);
+            }
+            self.sheet = Some(sheet.clone());
+            Ok(())
+        }
+    }
+
+    #[tonic::async_trait]
+    impl SheetSender for Slot {
+        async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>> {
+            self.sheet
+                .take()
+                .ok_or(CallError::Unit(PushOrPullError::Empty))
+        }
+
+        async fn take_back(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+            if self.refuse_take_back {
+                return Err(CallError::Rpc(Status::unavailable("Unit is down")));
+            }
+            self.put(sheet).map_err(CallError::Unit)
+        }
+
+        async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+            Ok(self.sheet.as_ref() == Some(sheet))
+        }
+    }
+
+    #[tonic::async_trait]
+    impl SheetSender for InputStack {
+        async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>> {
+            InputStack::push(self).map_err(CallError::Unit)
+        }
+
+        async fn take_back(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+            self.pull(sheet.clone());
+            Ok(())
+        }
+
+        async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+            Ok(self.returned().contains(sheet))
+        }
+    }
+
+    #[tonic::async_trait]
+    impl SheetReceiver for Slot {
+        async fn pull(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+            self.put(sheet).map_err(CallError::Unit)?;
+            if self.lose_pull_reply {
+                return Err(CallError::Rpc(Status::unavailable("Reply lost")));
+            }
+            Ok(())
+        }
+
+        async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+            Ok(self.sheet.as_ref() == Some(sheet))
+        }
+    }
+
+    #[tokio::test]
+    async fn handover_ok() {
+        let mut sender = Slot::with("Main-1");
+        let mut receiver = Slot::default();
+        let sheet = handover(&mut sender, &mut receiver).await.unwrap();
+        assert_eq!(Sheet::new("Main-1"), sheet);
+        assert_eq!(None, sender.sheet);
+        assert_eq!(Some(sheet), receiver.sheet);
+    }
+
+    #[tokio::test]
+    async fn empty_sender() {
+        let mut sender = Slot::default();
+        let mut receiver = Slot::default();
+        match handover(&mut sender, &mut receiver).await {
+            Err(HandoverError::Push(CallError::Unit(PushOrPullError::Empty))) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(None, receiver.sheet);
+    }
+
+    #[tokio::test]
+    async fn full_receiver_rolls_back() {
+        let mut sender = Slot::with("Main-1");
+        let mut receiver = Slot::with("Main-2");
+        match handover(&mut sender, &mut receiver).await {
+            Err(HandoverError::Pull(CallError::Unit(PushOrPullError::Full))) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(Some(Sheet::new("Main-1")), sender.sheet);
+        assert_eq!(Some(Sheet::new("Main-2")), receiver.sheet);
+    }
+
+    #[tokio::test]
+    async fn lost_reply_is_not_rolled_back() {
+        let mut sender = Slot::with("Main-1");
+        let mut receiver = Slot {
+            lose_pull_reply: true,
+            ..Slot::default()
+        };
+        assert!(handover(&mut sender, &mut receiver).await.is_ok());
+        assert_eq!(None, sender.sheet);
+        assert_eq!(Some(Sheet::new("Main-1")), receiver.sheet);
+    }
+
+    #[tokio::test]
+    async fn stranded() {
+        let mut sender = Slot {
+            sheet: Some(Sheet::new("Main-1")),
+            refuse_take_back: true,
+            ..Slot::default()
+        };
+        let mut receiver = Slot::with("Main-2");
+        match handover(&mut sender, &mut receiver).await {
+            Err(HandoverError::Stranded { sheet, .. }) => assert_eq!(Sheet::new("Main-1"), sheet),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_after_pull() {
+        // The receiver got the sheet before the crash
+        let mut sender = Slot::with("Main-2");
+        let mut receiver = Slot::with("Main-1");
+        let sheet = resume_handover(&mut sender, &mut receiver, &Sheet::new("Main-1"))
+            .await
+            .unwrap();
+        assert_eq!(Sheet::new("Main-1"), sheet);
+        // No further sheet was pushed
+        assert_eq!(Some(Sheet::new("Main-2")), sender.sheet);
+    }
+
+    #[tokio::test]
+    async fn resume_after_take_back() {
+        let mut sender = Slot::with("Main-1");
+        let mut receiver = Slot::default();
+        resume_handover(&mut sender, &mut receiver, &Sheet::new("Main-1"))
+            .await
+            .unwrap();
+        assert_eq!(None, sender.sheet);
+        assert_eq!(Some(Sheet::new("Main-1")), receiver.sheet);
+    }
+
+    #[tokio::test]
+    async fn resume_after_push() {
+        // The sheet was only in the memory of the crashed caller
+        let mut sender = Slot::default();
+        let mut receiver = Slot::default();
+        resume_handover(&mut sender, &mut receiver, &Sheet::new("Main-1"))
+            .await
+            .unwrap();
+        assert_eq!(Some(Sheet::new("Main-1")), receiver.sheet);
+
+        let mut receiver = Slot::with("Main-2");
+        match resume_handover(&mut sender, &mut receiver, &Sheet::new("Main-1")).await {
+            Err(HandoverError::Undelivered { sheet, .. }) => {
+                assert_eq!(Sheet::new("Main-1"), sheet)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_after_stack_rollback() {
+        // The receiver refused the sheet and the stack took it back
+        let mut stack = InputStack::new("Main", 2);
+        let mut receiver = Slot::with("Other-1");
+        assert!(handover(&mut stack, &mut receiver).await.is_err());
+        receiver.sheet = None;
+
+        let sheet = resume_handover(&mut stack, &mut receiver, &Sheet::new("Main-1"))
+            .await
+            .unwrap();
+        assert_eq!(Sheet::new("Main-1"), sheet);
+        assert_eq!(Some(Sheet::new("Main-1")), receiver.sheet);
+        // Handed out again rather than copied
+        assert_eq!(1, stack.paper_count());
+        assert_eq!(Ok(Sheet::new("Main-2")), stack.push());
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::{PlotterFunction, UnitKind};
+
+fn default_paper_count() -> u32 {
+    10
+}
+
+/// A unit served by this process on its own port
+#[derive(Debug, Deserialize)]
+pub struct HostedUnit {
+    name: String,
+    kind: UnitKind,
+    port: u16,
+    /// Port of the unit's HTTP metrics endpoint, none if left out
+    metrics_port: Option<u16>,
+    /// Functions of a plotter, all if left out
+    #[serde(default)]
+    functions: Vec<PlotterFunction>,
+    /// Sheets an input stack starts with
+    #[serde(default = "default_paper_count")]
+    paper_count: u32,
+    timing: Option<PathBuf>,
+    faults: Option<PathBuf>,
+    wear: Option<PathBuf>,
+}
+
+impl HostedUnit {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> UnitKind {
+        self.kind
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.metrics_port
+    }
+
+    pub fn functions(&self) -> &[PlotterFunction] {
+        &self.functions
+    }
+
+    pub fn paper_count(&self) -> u32 {
+        self.paper_count
+    }
+
+    /// Timing file of the unit, 100–500 ms per operation if missing
+    pub fn timing(&self) -> Option<&Path> {
+        self.timing.as_deref()
+    }
+
+    /// Faults injected into the unit's calls, none if missing
+    pub fn faults(&self) -> Option<&Path> {
+        self.faults.as_deref()
+    }
+
+    /// Wear model of a plotter or conveyor, which never wears if missing
+    pub fn wear(&self) -> Option<&Path> {
+        self.wear.as_deref()
+    }
+}
+
+#[derive(Debug)]
+pub enum HostingError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    /// Two units are configured to listen on the same port
+    DuplicatePort(u16),
+    /// Two units have the same name, so their logs and metrics could not be
+    /// told apart
+    DuplicateName(String),
+}
+
+impl Display for HostingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostingError::Io(e) => write!(f, "Could not read hosted units: {}", e),
+            HostingError::Parse(e) => write!(f, "Could not parse hosted units: {}", e),
+            HostingError::DuplicatePort(port) => {
+                write!(f, "More than one unit listens on port {}", port)
+            }
+            HostingError::DuplicateName(name) => {
+                write!(f, "More than one unit is named '{}'", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HostingError {}
+
+impl From<std::io::Error> for HostingError {
+    fn from(e: std::io::Error) -> Self {
+        HostingError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for HostingError {
+    fn from(e: toml::de::Error) -> Self {
+        HostingError::Parse(e)
+    }
+}
+
+#[derive(Deserialize)]
+struct HostingFile {
+    #[serde(rename = "unit", default)]
+    units: Vec<HostedUnit>,
+}
+
+/// Units a single process serves, each on its own port.
+///
+/// Timing and fault files are relative to the file the units are read from.
+#[derive(Debug)]
+pub struct Hosting {
+    units: Vec<HostedUnit>,
+}
+
+impl Hosting {
+    fn new(mut units: Vec<HostedUnit>) -> Result<Hosting, HostingError> {
+        let mut ports = Vec::new();
+        let mut names = Vec::new();
+        for unit in &units {
+            if names.contains(&&unit.name) {
+                return Err(HostingError::DuplicateName(unit.name.clone()));
+            }
+            names.push(&unit.name);
+            for &port in std::iter::once(&unit.port).chain(&unit.metrics_port) {
+                if ports.contains(&port) {
+                    return Err(HostingError::DuplicatePort(port));
+                }
+                ports.push(port);
+            }
+        }
+        for unit in &mut units {
+            if unit.kind == UnitKind::Plotter && unit.functions.is_empty() {
+                unit.functions = PlotterFunction::all().to_vec();
+            }
+        }
+        Ok(Hosting { units })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Hosting, HostingError> {
+        let mut hosting: Hosting = std::fs::read_to_string(&path)?.parse()?;
+        if let Some(dir) = path.as_ref().parent() {
+            for unit in &mut hosting.units {
+                unit.timing = unit.timing.take().map(|p| dir.join(p));
+                unit.faults = unit.faults.take().map(|p| dir.join(p));
+                unit.wear = unit.wear.take().map(|p| dir.join(p));
+            }
+        }
+        Ok(hosting)
+    }
+
+    pub fn units(&self) -> &[HostedUnit] {
+        &self.units
+    }
+}
+
+impl FromStr for Hosting {
+    type Err = HostingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let file: HostingFile = toml::from_str(s)?;
+        Hosting::new(file.units)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTING: &str = r#"
+        [[unit]]
+        name = "Plotter 1"
+        kind = "Plotter"
+        port = 5000
+        metrics_port = 9000
+        functions = ["DrawRed"]
+        timing = "timing.toml"
+        wear = "wear.toml"
+
+        [[unit]]
+        name = "Main"
+        kind = "InputStack"
+        port = 5004
+        paper_count = 3
+    "#;
+
+    #[test]
+    fn parse() {
+        let hosting: Hosting = HOSTING.parse().unwrap();
+        let plotter = &hosting.units()[0];
+        assert_eq!("Plotter 1", plotter.name());
+        assert_eq!(UnitKind::Plotter, plotter.kind());
+        assert_eq!(5000, plotter.port());
+        assert_eq!(Some(9000), plotter.metrics_port());
+        assert_eq!(&[PlotterFunction::DrawRed], plotter.functions());
+        assert_eq!(Some(Path::new("timing.toml")), plotter.timing());
+        assert_eq!(Some(Path::new("wear.toml")), plotter.wear());
+
+        let stack = &hosting.units()[1];
+        assert_eq!(3, stack.paper_count());
+        assert!(stack.functions().is_empty());
+        assert_eq!(None, stack.faults());
+        assert_eq!(None, stack.wear());
+        assert_eq!(None, stack.metrics_port());
+    }
+
+    #[test]
+    fn duplicate_port() {
+        let hosting = HOSTING.replace("5004", "5000");
+        match hosting.parse::<Hosting>() {
+            Err(HostingError::DuplicatePort(port)) => assert_eq!(5000, port),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let hosting = HOSTING.replace("5004", "9000");
+        match hosting.parse::<Hosting>() {
+            Err(HostingError::DuplicatePort(port)) => assert_eq!(9000, port),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let hosting = HOSTING.replace("\"Main\"", "\"Plotter 1\"");
+        match hosting.parse::<Hosting>() {
+            Err(HostingError::DuplicateName(name)) => assert_eq!("Plotter 1", name),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn factory() {
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let hosting = Hosting::from_file(Path::new(dir).join("factory.toml")).unwrap();
+        assert_eq!(8, hosting.units().len());
+        for unit in hosting.units() {
+            assert_eq!(
+                Some(Path::new(dir).join("timing.toml").as_path()),
+                unit.timing()
+            );
+        }
+    }
+}
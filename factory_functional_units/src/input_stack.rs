@@ -1,15 +1,21 @@
-use crate::PushOrPullError;
+use crate::{PushOrPullError, Sheet};
 
 pub struct InputStack {
     name: String,
+    start_count: u32,
     paper_count: u32,
+    next_id: u32,
+    returned: Vec<Sheet>,
 }
 
 impl InputStack {
     pub fn new(name: &str, start_count: u32) -> InputStack {
         InputStack {
             name: String::from(name),
+            start_count,
             paper_count: start_count,
+            next_id: 1,
+            returned: Vec::new(),
         }
     }
 
@@ -18,17 +24,46 @@ impl InputStack {
     }
 
     pub fn paper_count(&self) -> u32 {
-        self.paper_count
+        self.paper_count + self.returned.len() as u32
     }
 
-    pub fn push(&mut self) -> Result<(), PushOrPullError> {
-        if self.paper_count == 0 {
+    /// Sheets put back on top of the stack, the last one is handed out next
+    pub fn returned(&self) -> &[Sheet] {
+        &self.returned
+    }
+
+    /// Hands out a blank sheet. Sheets are numbered in the order they leave
+    /// the stack, prefixed with the name of the stack.
+    pub fn push(&mut self) -> Result<Sheet, PushOrPullError> {
+        if let Some(sheet) = self.returned.pop() {
+            Ok(sheet)
+        } else if self.paper_count == 0 {
             Err(PushOrPullError::Empty)
         } else {
             self.paper_count -= 1;
-            Ok(())
+            let sheet = Sheet::new(&format!("{}-{}", self.name, self.next_id));
+            self.next_id += 1;
+            Ok(sheet)
         }
     }
+
+    /// Puts a sheet back on top of the stack, so it is the next one handed
+    /// out. Used to undo a handover the receiver refused.
+    pub fn pull(&mut self, sheet: Sheet) {
+        self.returned.push(sheet);
+    }
+
+    /// Replaces all sheets, including returned ones, with `count` blank ones.
+    /// Sheet ids keep counting up, so they stay unique.
+    pub fn set_paper_count(&mut self, count: u32) {
+        self.returned.clear();
+        self.paper_count = count;
+    }
+
+    /// Sets the paper count back to the one the stack started with
+    pub fn refill(&mut self) {
+        self.set_paper_count(self.start_count);
+    }
 }
 
 #[cfg(test)]
@@ -49,9 +84,12 @@ mod tests {
         let paper_count = 10;
         let mut stack = InputStack::new("Main", paper_count);
 
-        stack.push()?;
+        assert_eq!(Sheet::new("Main-1"), stack.push()?);
         assert_eq!(paper_count - 1, stack.paper_count());
 
+        assert_eq!(Sheet::new("Main-2"), stack.push()?);
+        assert_eq!(paper_count - 2, stack.paper_count());
+
         Ok(())
     }
 
@@ -61,10 +99,42 @@ mod tests {
         let mut stack = InputStack::new("Main", paper_count);
 
         for i in 1..paper_count + 1 {
-            assert_eq!(Ok(()), stack.push());
+            assert!(stack.push().is_ok());
             assert_eq!(paper_count - i, stack.paper_count());
         }
 
         assert_eq!(Err(PushOrPullError::Empty), stack.push());
     }
+
+    #[test]
+    fn set_paper_count() -> Result<(), PushOrPullError> {
+        let mut stack = InputStack::new("Main", 2);
+        let sheet = stack.push()?;
+        stack.pull(sheet);
+
+        stack.set_paper_count(1);
+        assert_eq!(1, stack.paper_count());
+        assert_eq!(Sheet::new("Main-2"), stack.push()?);
+
+        stack.refill();
+        assert_eq!(2, stack.paper_count());
+        Ok(())
+    }
+
+    #[test]
+    fn pull_returned_sheet() -> Result<(), PushOrPullError> {
+        let mut stack = InputStack::new("Main", 1);
+
+        let sheet = stack.push()?;
+        assert_eq!(0, stack.paper_count());
+
+        stack.pull(sheet);
+        assert_eq!(1, stack.paper_count());
+        assert_eq!(&[Sheet::new("Main-1")], stack.returned());
+        assert_eq!(Sheet::new("Main-1"), stack.push()?);
+        assert!(stack.returned().is_empty());
+        assert_eq!(Err(PushOrPullError::Empty), stack.push());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::{Orientation, PlotterFunction};
+
+const SIDES: [Orientation; 4] = [
+    Orientation::North,
+    Orientation::East,
+    Orientation::South,
+    Orientation::West,
+];
+
+#[derive(PartialEq, Debug, Copy, Clone, Deserialize)]
+pub enum UnitKind {
+    Plotter,
+    Conveyor,
+    InputStack,
+    OutputStack,
+}
+
+impl Display for UnitKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnitKind::Plotter => write!(f, "Plotter"),
+            UnitKind::Conveyor => write!(f, "Conveyor"),
+            UnitKind::InputStack => write!(f, "InputStack"),
+            UnitKind::OutputStack => write!(f, "OutputStack"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LayoutError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    DuplicateUnit(String),
+    /// A unit is connected to a unit that is not part of the layout
+    UnknownNeighbour {
+        unit: String,
+        side: Orientation,
+        neighbour: String,
+    },
+    /// The neighbour does not connect back through the opposite side
+    Asymmetric {
+        unit: String,
+        side: Orientation,
+        neighbour: String,
+    },
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::Io(e) => write!(f, "Could not read layout: {}", e),
+            LayoutError::Parse(e) => write!(f, "Could not parse layout: {}", e),
+            LayoutError::DuplicateUnit(id) => write!(f, "Unit '{}' is defined twice", id),
+            LayoutError::UnknownNeighbour {
+                unit,
+                side,
+                neighbour,
+            } => write!(
+                f,
+                "Unit '{}' is connected to unknown unit '{}' on its {} side",
+                unit, neighbour, side
+            ),
+            LayoutError::Asymmetric {
+                unit,
+                side,
+                neighbour,
+            } => write!(
+                f,
+                "Unit '{}' is connected to '{}' on its {} side, but '{}' is not connected back on its {} side",
+                unit,
+                neighbour,
+                side,
+                neighbour,
+                side.inverse()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl From<std::io::Error> for LayoutError {
+    fn from(e: std::io::Error) -> Self {
+        LayoutError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for LayoutError {
+    fn from(e: toml::de::Error) -> Self {
+        LayoutError::Parse(e)
+    }
+}
+
+#[derive(Deserialize)]
+struct LayoutFile {
+    #[serde(rename = "unit", default)]
+    units: Vec<UnitEntry>,
+}
+
+#[derive(Deserialize)]
+struct UnitEntry {
+    id: String,
+    kind: UnitKind,
+    address: String,
+    #[serde(default)]
+    functions: Vec<PlotterFunction>,
+    north: Option<String>,
+    east: Option<String>,
+    south: Option<String>,
+    west: Option<String>,
+}
+
+/// A functional unit placed on the shop floor
+#[derive(Debug)]
+pub struct UnitLayout {
+    id: String,
+    kind: UnitKind,
+    address: String,
+    functions: Vec<PlotterFunction>,
+    connections: HashMap<Orientation, String>,
+}
+
+impl UnitLayout {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn kind(&self) -> UnitKind {
+        self.kind
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Functions a plotter offers
+    pub fn functions(&self) -> &[PlotterFunction] {
+        &self.functions
+    }
+
+    /// Id of the unit connected to the given side
+    pub fn connection(&self, side: Orientation) -> Option<&str> {
+        self.connections.get(&side).map(String::as_str)
+    }
+
+    /// Connected units with the side they are connected to
+    pub fn connections(&self) -> impl Iterator<Item = (Orientation, &str)> {
+        SIDES
+            .iter()
+            .filter_map(move |&side| self.connection(side).map(|id| (side, id)))
+    }
+}
+
+impl From<UnitEntry> for UnitLayout {
+    fn from(entry: UnitEntry) -> Self {
+        let mut connections = HashMap::new();
+        let sides = vec![
+            (Orientation::North, entry.north),
+            (Orientation::East, entry.east),
+            (Orientation::South, entry.south),
+            (Orientation::West, entry.west),
+        ];
+        for (side, neighbour) in sides {
+            if let Some(neighbour) = neighbour {
+                connections.insert(side, neighbour);
+            }
+        }
+        UnitLayout {
+            id: entry.id,
+            kind: entry.kind,
+            address: entry.address,
+            functions: entry.functions,
+            connections,
+        }
+    }
+}
+
+/// Describes which units are on the shop floor and how they are connected
+/// through their North, East, South and West ports.
+///
+/// Connections are symmetric: if unit A has B on its East side, B must have
+/// A on its West side.
+#[derive(Debug)]
+pub struct Layout {
+    units: Vec<UnitLayout>,
+}
+
+impl Layout {
+    fn new(units: Vec<UnitLayout>) -> Result<Layout, LayoutError> {
+        let layout = Layout { units };
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Layout, LayoutError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    pub fn units(&self) -> &[UnitLayout] {
+        &self.units
+    }
+
+    pub fn unit(&self, id: &str) -> Option<&UnitLayout> {
+        self.units.iter().find(|u| u.id == id)
+    }
+
+    /// Unit connected to the given side of unit `id`
+    pub fn neighbour(&self, id: &str, side: Orientation) -> Option<&UnitLayout> {
+        self.unit(id)
+            .and_then(|u| u.connection(side))
+            .and_then(|n| self.unit(n))
+    }
+
+    fn validate(&self) -> Result<(), LayoutError> {
+        for (i, unit) in self.units.iter().enumerate() {
+            if self.units[..i].iter().any(|u| u.id == unit.id) {
+                return Err(LayoutError::DuplicateUnit(unit.id.clone()));
+            }
+        }
+
+        for unit in &self.units {
+            for (side, neighbour) in unit.connections() {
+                let other = self
+                    .unit(neighbour)
+                    .ok_or_else(|| LayoutError::UnknownNeighbour {
+                        unit: unit.id.clone(),
+                        side,
+                        neighbour: neighbour.to_owned(),
+                    })?;
+                if other.connection(side.inverse()) != Some(unit.id()) {
+                    return Err(LayoutError::Asymmetric {
+                        unit: unit.id.clone(),
+                        side,
+                        neighbour: neighbour.to_owned(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Layout {
+    type Err = LayoutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let file: LayoutFile = toml::from_str(s)?;
+        Layout::new(file.units.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r#"
+        [[unit]]
+        id = "input"
+        kind = "InputStack"
+        address = "http://localhost:5004"
+        east = "conv1"
+
+        [[unit]]
+        id = "conv1"
+        kind = "Conveyor"
+        address = "http://localhost:5006"
+        west = "input"
+        north = "plotter1"
+        east = "output"
+
+        [[unit]]
+        id = "plotter1"
+        kind = "Plotter"
+        address = "http://localhost:5000"
+        functions = ["DrawRed", "DrawBlue"]
+        south = "conv1"
+
+        [[unit]]
+        id = "output"
+        kind = "OutputStack"
+        address = "http://localhost:5005"
+        west = "conv1"
+    "#;
+
+    #[test]
+    fn parse() {
+        let layout: Layout = LAYOUT.parse().unwrap();
+        assert_eq!(4, layout.units().len());
+
+        let conv = layout.unit("conv1").unwrap();
+        assert_eq!(UnitKind::Conveyor, conv.kind());
+        assert_eq!("http://localhost:5006", conv.address());
+        assert!(conv.functions().is_empty());
+        assert_eq!(Some("plotter1"), conv.connection(Orientation::North));
+        assert_eq!(None, conv.connection(Orientation::South));
+        assert_eq!(
+            vec![
+                (Orientation::North, "plotter1"),
+                (Orientation::East, "output"),
+                (Orientation::West, "input"),
+            ],
+            conv.connections().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let layout: Layout = LAYOUT.parse().unwrap();
+        assert_eq!(
+            &[PlotterFunction::DrawRed, PlotterFunction::DrawBlue],
+            layout.unit("plotter1").unwrap().functions()
+        );
+    }
+
+    #[test]
+    fn invalid_function() {
+        let layout = LAYOUT.replace("\"DrawBlue\"", "\"DrawPurple\"");
+        match layout.parse::<Layout>() {
+            Err(LayoutError::Parse(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn neighbour() {
+        let layout: Layout = LAYOUT.parse().unwrap();
+        assert_eq!(
+            "plotter1",
+            layout.neighbour("conv1", Orientation::North).unwrap().id()
+        );
+        assert!(layout.neighbour("conv1", Orientation::South).is_none());
+        assert!(layout.neighbour("unknown", Orientation::South).is_none());
+    }
+
+    #[test]
+    fn asymmetric() {
+        let layout = LAYOUT.replace("south = \"conv1\"", "west = \"conv1\"");
+        match layout.parse::<Layout>() {
+            Err(LayoutError::Asymmetric { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_neighbour() {
+        let layout = LAYOUT.replace("north = \"plotter1\"", "north = \"plotter2\"");
+        match layout.parse::<Layout>() {
+            Err(LayoutError::UnknownNeighbour { neighbour, .. }) => {
+                assert_eq!("plotter2", neighbour)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_unit() {
+        let layout = LAYOUT.replace("id = \"plotter1\"", "id = \"input\"");
+        match layout.parse::<Layout>() {
+            Err(LayoutError::DuplicateUnit(id)) => assert_eq!("input", id),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compose_layout() {
+        let layout =
+            Layout::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/layout.toml")).unwrap();
+        assert_eq!(8, layout.units().len());
+    }
+
+    #[test]
+    fn local_layout() {
+        let layout =
+            Layout::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/layout.local.toml")).unwrap();
+        assert_eq!(
+            "http://localhost:5006",
+            layout.unit("conv1").unwrap().address()
+        );
+    }
+
+    #[test]
+    fn invalid_kind() {
+        let layout = LAYOUT.replace("\"Plotter\"", "\"Printer\"");
+        match layout.parse::<Layout>() {
+            Err(LayoutError::Parse(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -1,23 +1,52 @@
 use std::cmp::PartialEq;
 use std::fmt::{Display, Formatter};
 use std::marker::Copy;
+use std::str::FromStr;
 
+use serde::Deserialize;
+
+pub use self::client::{
+    CallError, ConveyorClient, ConveyorStatus, ErrorDetails, HealthClient, HistoryEvent,
+    InputStackClient, InputStackStatus, OutputStackClient, OutputStackStatus, PlotterClient,
+    PlotterStatus, StatusWatch,
+};
 pub use self::conveyor::*;
+pub use self::faults::{Fault, FaultModel, Faults, FaultsError};
+pub use self::handover::{handover, resume_handover, HandoverError, SheetReceiver, SheetSender};
+pub use self::hosting::{HostedUnit, Hosting, HostingError};
 pub use self::input_stack::*;
+pub use self::layout::{Layout, LayoutError, UnitKind, UnitLayout};
+pub use self::logging::{logger, LogFormat};
 pub use self::output_stack::*;
 pub use self::plotter::*;
 pub use self::server::{
-    ConveyorServer, ConveyorServerState, Delayer, InputStackServer, InputStackServerState,
-    OutputStackServer, OutputStackServerState, PlotterServer, PlotterServerState,
+    serve_metrics, Condition, ConveyorServer, ConveyorServerState, Delayer, Health, HealthServer,
+    HealthServerState, InputStackServer, InputStackServerState, Metrics, OutputStackServer,
+    OutputStackServerState, PlotterServer, PlotterServerState, ReflectionServerState,
+    ServerReflectionServer,
 };
+pub use self::sheet::Sheet;
+pub use self::timing::{Action, Clock, Distribution, Timing, TimingError};
+pub use self::trace::{SpanExporter, SpanKind, TraceContext, TraceSpan};
+pub use self::wear::{Usage, WearError, WearModel};
 
+mod client;
 mod conveyor;
+mod faults;
+mod handover;
+mod hosting;
 mod input_stack;
+mod layout;
+mod logging;
 mod output_stack;
 mod plotter;
 mod server;
+mod sheet;
+mod timing;
+mod trace;
+mod wear;
 
-#[derive(PartialEq, Debug, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
 pub enum Orientation {
     North,
     East,
@@ -34,6 +63,22 @@ impl Orientation {
             Orientation::West => Orientation::East,
         }
     }
+
+    /// Number of 90° steps to turn to `target` in the shorter direction
+    pub fn steps_to(&self, target: Orientation) -> u32 {
+        let diff = (target.quarter() + 4 - self.quarter()) % 4;
+        diff.min(4 - diff)
+    }
+
+    /// Clockwise quarter turns from North
+    fn quarter(&self) -> u32 {
+        match self {
+            Orientation::North => 0,
+            Orientation::East => 1,
+            Orientation::South => 2,
+            Orientation::West => 3,
+        }
+    }
 }
 
 impl Display for Orientation {
@@ -47,10 +92,63 @@ impl Display for Orientation {
     }
 }
 
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone, Deserialize)]
+pub enum PlotterFunction {
+    DrawRed,
+    DrawGreen,
+    DrawBlue,
+    DrawYellow,
+}
+
+impl Display for PlotterFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlotterFunction::DrawRed => write!(f, "DrawRed"),
+            PlotterFunction::DrawGreen => write!(f, "DrawGreen"),
+            PlotterFunction::DrawBlue => write!(f, "DrawBlue"),
+            PlotterFunction::DrawYellow => write!(f, "DrawYellow"),
+        }
+    }
+}
+
+impl FromStr for PlotterFunction {
+    type Err = String;
+
+    /// Parses the name case-insensitively, with or without underscores
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.replace('_', "").to_lowercase().as_str() {
+            "drawred" => Ok(PlotterFunction::DrawRed),
+            "drawgreen" => Ok(PlotterFunction::DrawGreen),
+            "drawblue" => Ok(PlotterFunction::DrawBlue),
+            "drawyellow" => Ok(PlotterFunction::DrawYellow),
+            _ => Err(format!("Unknown plotter function '{}'", s)),
+        }
+    }
+}
+
+impl PlotterFunction {
+    pub fn variants() -> [&'static str; 4] {
+        ["DrawRed", "DrawGreen", "DrawBlue", "DrawYellow"]
+    }
+
+    pub fn all() -> [PlotterFunction; 4] {
+        [
+            PlotterFunction::DrawRed,
+            PlotterFunction::DrawGreen,
+            PlotterFunction::DrawBlue,
+            PlotterFunction::DrawYellow,
+        ]
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub enum PushOrPullError {
     Empty,
     Full,
+    /// The sheet got stuck and stays where it was
+    Jammed,
+    /// The pushed sheet fell off the line
+    Dropped,
 }
 
 #[cfg(test)]
@@ -67,5 +165,37 @@ mod tests {
             assert_eq!(Orientation::South.inverse(), Orientation::North);
             assert_eq!(Orientation::West.inverse(), Orientation::East);
         }
+
+        #[test]
+        fn steps_to() {
+            assert_eq!(0, Orientation::East.steps_to(Orientation::East));
+            assert_eq!(1, Orientation::North.steps_to(Orientation::East));
+            assert_eq!(1, Orientation::North.steps_to(Orientation::West));
+            assert_eq!(2, Orientation::West.steps_to(Orientation::East));
+            assert_eq!(1, Orientation::West.steps_to(Orientation::North));
+        }
+    }
+
+    mod plotter_function {
+        use super::*;
+
+        #[test]
+        fn from_str() {
+            assert_eq!(Ok(PlotterFunction::DrawRed), "DrawRed".parse());
+            assert_eq!(Ok(PlotterFunction::DrawGreen), "drawgreen".parse());
+            assert_eq!(Ok(PlotterFunction::DrawBlue), "DRAW_BLUE".parse());
+            assert!("DrawPurple".parse::<PlotterFunction>().is_err());
+        }
+
+        #[test]
+        fn variants() {
+            for (name, function) in PlotterFunction::variants()
+                .iter()
+                .zip(PlotterFunction::all().iter())
+            {
+                assert_eq!(Ok(*function), name.parse());
+                assert_eq!(*name, function.to_string());
+            }
+        }
     }
 }
@@ -0,0 +1,121 @@
+use std::io::Write;
+
+use tracing::{Dispatch, Level};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// How log lines are written
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum LogFormat {
+    /// A line of text for people to read
+    Text,
+    /// A JSON object per line for log aggregators
+    Json,
+}
+
+/// Target prefix of the events of this crate and its binary
+const CRATE: &str = "factory_functional_units";
+
+/// Events of this crate up to `level`, only warnings and errors of other
+/// crates. `RUST_LOG` replaces the filter if set.
+fn filter(level: Level) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::default()
+            .add_directive(LevelFilter::WARN.into())
+            .add_directive(
+                format!("{}={}", CRATE, level)
+                    .parse()
+                    .expect("Crate name and level make a valid directive"),
+            )
+    })
+}
+
+/// Logs to standard output with the fields of the spans the events
+/// happened in. Timestamps are wall-clock time, also under a virtual clock.
+pub fn logger(level: Level, format: LogFormat) -> Dispatch {
+    with_writer(level, format, std::io::stdout)
+}
+
+fn with_writer<W, M>(level: Level, format: LogFormat, writer: M) -> Dispatch
+where
+    W: Write,
+    M: MakeWriter<Writer = W> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter(level))
+        .with_writer(writer)
+        .with_ansi(false);
+    match format {
+        LogFormat::Text => Dispatch::new(builder.finish()),
+        LogFormat::Json => Dispatch::new(
+            builder
+                .json()
+                .with_current_span(false)
+                .with_span_list(true)
+                .finish(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log(level: Level, format: LogFormat) -> String {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let logger = with_writer(level, format, move || writer.clone());
+        tracing::dispatcher::with_default(&logger, || {
+            let span = tracing::info_span!(
+                target: "factory_functional_units::server",
+                "call",
+                unit = "Plotter 1",
+                method = "Plot"
+            );
+            let _entered = span.enter();
+            tracing::debug!(target: "factory_functional_units::server", "Sleeping");
+            tracing::info!(target: "factory_functional_units::server", result = "Ok", "Plotted \"red\"");
+            tracing::info!(target: "hyper::server", "Accepted");
+        });
+        let out = buffer.0.lock().unwrap().clone();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn text_lines() {
+        let out = log(Level::INFO, LogFormat::Text);
+        assert_eq!(1, out.lines().count(), "{}", out);
+        assert!(out.contains(
+            " INFO call{unit=\"Plotter 1\" method=\"Plot\"}: factory_functional_units::server: \
+             Plotted \"red\" result=\"Ok\"\n"
+        ));
+        assert_eq!(2, log(Level::DEBUG, LogFormat::Text).lines().count());
+    }
+
+    #[test]
+    fn json_lines() {
+        let out = log(Level::INFO, LogFormat::Json);
+        let line: serde_json::Value = serde_json::from_str(out.trim()).unwrap();
+        assert_eq!("INFO", line["level"]);
+        assert_eq!("Plotted \"red\"", line["fields"]["message"]);
+        assert_eq!("Ok", line["fields"]["result"]);
+        assert_eq!("call", line["spans"][0]["name"]);
+        assert_eq!("Plotter 1", line["spans"][0]["unit"]);
+        assert!(line["timestamp"].is_string());
+    }
+}
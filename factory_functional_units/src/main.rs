@@ -1,8 +1,12 @@
-use clap::{arg_enum, value_t, App, Arg};
-use tonic::transport::Server;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use clap::{arg_enum, value_t, values_t, App, Arg, ArgMatches};
+use tonic::transport::{NamedService, Server};
+use tracing::{error, info, Level};
 
 use factory_functional_units::*;
-use std::time::Duration;
 
 arg_enum! {
     #[derive(PartialEq, Debug)]
@@ -14,8 +18,19 @@ arg_enum! {
     }
 }
 
+impl From<UnitKind> for Unit {
+    fn from(kind: UnitKind) -> Self {
+        match kind {
+            UnitKind::Plotter => Unit::Plotter,
+            UnitKind::Conveyor => Unit::Conveyor,
+            UnitKind::InputStack => Unit::InputStack,
+            UnitKind::OutputStack => Unit::OutputStack,
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), Box<dyn Error>> {
     let matches = App::new("factory_functional_units")
         .version("0.1.3")
         .arg(
@@ -24,7 +39,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .long("port")
                 .value_name("PORT")
                 .help("Sets the port the service listens to")
-                .required(true)
+                .required_unless("config")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("metrics-port")
+                .long("metrics-port")
+                .value_name("PORT")
+                .help("Serves the unit's metrics over HTTP at /metrics on this port")
+                .conflicts_with("config")
                 .takes_value(true),
         )
         .arg(
@@ -35,7 +58,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Defines which unit to run (only one unit can run)")
                 .possible_values(&Unit::variants())
                 .case_insensitive(true)
-                .required(true)
+                .required_unless_one(&["probe", "config"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("Hosts all units listed in the file, each on its own port")
+                .conflicts_with_all(&["unit", "port"])
                 .takes_value(true),
         )
         .arg(
@@ -45,51 +77,360 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Name of the unit (visible when querying status)")
                 .default_value("Unnamed"),
         )
+        .arg(
+            Arg::with_name("paper-count")
+                .long("paper-count")
+                .value_name("COUNT")
+                .help("Number of sheets an input stack starts with")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("function")
+                .short("f")
+                .long("function")
+                .value_name("FUNCTION")
+                .help("Function a plotter offers (all functions if omitted)")
+                .possible_values(&PlotterFunction::variants())
+                .case_insensitive(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("timing")
+                .short("t")
+                .long("timing")
+                .value_name("FILE")
+                .help("Durations of the unit's operations (100-500 ms each if omitted)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("faults")
+                .long("faults")
+                .value_name("FILE")
+                .help("Faults to inject into the unit's calls (none if omitted)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("wear")
+                .long("wear")
+                .value_name("FILE")
+                .help("Wear model of a plotter or conveyor (never wears if omitted)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Seeds the random delays to get the same timing on every run")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("time-scale")
+                .long("time-scale")
+                .value_name("FACTOR")
+                .help("Runs faster than the real line, 3600 runs an hour in a second")
+                .validator(|v| match v.parse::<f64>() {
+                    Ok(factor) if factor > 0.0 => Ok(()),
+                    _ => Err(format!("Expected a positive factor, got '{}'", v)),
+                })
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("virtual-time")
+                .long("virtual-time")
+                .help(
+                    "Answers without any delay, only advancing the unit's virtual time; \
+                     history, spans and uptime follow it, log lines keep the wall clock",
+                )
+                .conflicts_with("time-scale"),
+        )
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .value_name("LEVEL")
+                .help("Most detailed level logged, debug adds statuses and delays; RUST_LOG overrides it")
+                .possible_values(&["error", "warn", "info", "debug", "trace"])
+                .case_insensitive(true)
+                .default_value("info"),
+        )
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("Logs lines of text or JSON objects for log aggregators")
+                .possible_values(&["text", "json"])
+                .case_insensitive(true)
+                .default_value("text"),
+        )
+        .arg(
+            Arg::with_name("trace-file")
+                .long("trace-file")
+                .value_name("FILE")
+                .help("Appends a span for every call to FILE, as OTLP JSON lines")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("probe")
+                .long("probe")
+                .help("Checks the health of the unit listening to PORT on this host and exits"),
+        )
         .get_matches();
 
-    let port = matches.value_of("port").unwrap();
+    let level = match matches
+        .value_of("log-level")
+        .unwrap()
+        .to_lowercase()
+        .as_str()
+    {
+        "error" => Level::ERROR,
+        "warn" => Level::WARN,
+        "debug" => Level::DEBUG,
+        "trace" => Level::TRACE,
+        _ => Level::INFO,
+    };
+    let format = if matches
+        .value_of("log-format")
+        .unwrap()
+        .eq_ignore_ascii_case("json")
+    {
+        LogFormat::Json
+    } else {
+        LogFormat::Text
+    };
+    tracing::dispatcher::set_global_default(logger(level, format))?;
+    let exporter = match matches.value_of("trace-file") {
+        Some(path) => Some(Arc::new(SpanExporter::to_file(path)?)),
+        None => None,
+    };
+
+    if let Some(path) = matches.value_of("config") {
+        let hosting = Hosting::from_file(path)?;
+        let mut servers = Vec::new();
+        for (i, unit) in hosting.units().iter().enumerate() {
+            let timing = unit
+                .timing()
+                .or_else(|| matches.value_of("timing").map(Path::new));
+            let faults = unit
+                .faults()
+                .or_else(|| matches.value_of("faults").map(Path::new));
+            let (delayer, faults) = behaviour(&matches, timing, faults, i as u64)?;
+            let wear = wear_model(
+                unit.wear()
+                    .or_else(|| matches.value_of("wear").map(Path::new)),
+            )?;
+            servers.push(tokio::spawn(serve(
+                unit.kind().into(),
+                unit.name().to_owned(),
+                unit.functions().to_vec(),
+                unit.paper_count(),
+                unit.port(),
+                unit.metrics_port(),
+                delayer,
+                faults,
+                wear,
+                exporter.clone(),
+            )));
+        }
+        for server in servers {
+            server.await??;
+        }
+        return Ok(());
+    }
+
+    let port = value_t!(matches, "port", u16)?;
+    if matches.is_present("probe") {
+        let mut client = HealthClient::connect(format!("http://127.0.0.1:{}", port)).await?;
+        let serving = client.check("").await?;
+        println!(
+            "Unit is {}",
+            if serving { "serving" } else { "not serving" }
+        );
+        std::process::exit(if serving { 0 } else { 1 });
+    }
     let unit: Unit = value_t!(matches, "unit", Unit).unwrap();
-    let name = matches.value_of("name").unwrap();
+    let name = matches.value_of("name").unwrap().to_owned();
+    let functions = values_t!(matches, "function", PlotterFunction)
+        .unwrap_or_else(|_| PlotterFunction::all().to_vec());
+    let paper_count = value_t!(matches, "paper-count", u32)?;
+    let metrics_port = match matches.value_of("metrics-port") {
+        Some(_) => Some(value_t!(matches, "metrics-port", u16)?),
+        None => None,
+    };
+    let (delayer, faults) = behaviour(
+        &matches,
+        matches.value_of("timing").map(Path::new),
+        matches.value_of("faults").map(Path::new),
+        0,
+    )?;
+    let wear = wear_model(matches.value_of("wear").map(Path::new))?;
+    serve(
+        unit,
+        name,
+        functions,
+        paper_count,
+        port,
+        metrics_port,
+        delayer,
+        faults,
+        wear,
+        exporter,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Delays and faults of a unit with the clock and seed given on the command
+/// line. Units hosted together add their `index` to the seed to differ.
+fn behaviour(
+    matches: &ArgMatches,
+    timing: Option<&Path>,
+    faults: Option<&Path>,
+    index: u64,
+) -> Result<(Delayer, Faults), Box<dyn Error>> {
+    let timing = match timing {
+        Some(path) => Timing::from_file(path)?,
+        None => Timing::default(),
+    };
+    let clock = if matches.is_present("virtual-time") {
+        Clock::Virtual
+    } else if matches.is_present("time-scale") {
+        Clock::Scaled(value_t!(matches, "time-scale", f64)?)
+    } else {
+        Clock::Real
+    };
+    let mut delayer = Delayer::with_timing(timing).with_clock(clock);
+    let mut faults = match faults {
+        Some(path) => Faults::from_file(path)?,
+        None => Faults::none(),
+    };
+    if matches.is_present("seed") {
+        let seed = value_t!(matches, "seed", u64)?.wrapping_add(index);
+        delayer = delayer.seeded(seed);
+        faults = faults.seeded(seed);
+    }
+    Ok((delayer, faults))
+}
 
-    let delayer = Delayer::new(Duration::from_millis(100), Duration::from_millis(500));
+fn wear_model(path: Option<&Path>) -> Result<WearModel, WearError> {
+    match path {
+        Some(path) => WearModel::from_file(path),
+        None => Ok(WearModel::none()),
+    }
+}
 
-    let addr = format!("0.0.0.0:{}", port).parse()?;
-    println!("Running unit {} '{}' and binding to {}", unit, name, addr);
+/// Serves a unit with its health and reflection services, its metrics if
+/// given a port for them and its spans if given an exporter
+#[allow(clippy::too_many_arguments)]
+async fn serve(
+    unit: Unit,
+    name: String,
+    functions: Vec<PlotterFunction>,
+    paper_count: u32,
+    port: u16,
+    metrics_port: Option<u16>,
+    delayer: Delayer,
+    faults: Faults,
+    wear: WearModel,
+    exporter: Option<Arc<SpanExporter>>,
+) -> Result<(), tonic::transport::Error> {
+    let addr = ([0, 0, 0, 0], port).into();
+    info!(kind = %unit, unit = name.as_str(), address = %addr, "Serving unit");
     match unit {
         Unit::Plotter => {
-            let plotter = Plotter::new(name);
+            let plotter = Plotter::new(&name, &functions);
+            let mut state = PlotterServerState::new(plotter, delayer)
+                .with_faults(faults)
+                .with_wear(wear);
+            if let Some(exporter) = exporter {
+                state = state.with_exporter(exporter);
+            }
+            spawn_metrics(state.metrics(), metrics_port);
+            let service = PlotterServer::<PlotterServerState>::NAME;
+            let health = health(state.health(), service);
             Server::builder()
-                .add_service(PlotterServer::new(PlotterServerState::new(
-                    plotter, delayer,
-                )))
+                .add_service(PlotterServer::new(state))
+                .add_service(health)
+                .add_service(reflection(service))
                 .serve(addr)
-                .await?;
+                .await
         }
         Unit::Conveyor => {
-            let conv = Conveyor::new(name);
+            let conv = Conveyor::new(&name);
+            let mut state = ConveyorServerState::new(conv, delayer)
+                .with_faults(faults)
+                .with_wear(wear);
+            if let Some(exporter) = exporter {
+                state = state.with_exporter(exporter);
+            }
+            spawn_metrics(state.metrics(), metrics_port);
+            let service = ConveyorServer::<ConveyorServerState>::NAME;
+            let health = health(state.health(), service);
             Server::builder()
-                .add_service(ConveyorServer::new(ConveyorServerState::new(conv, delayer)))
+                .add_service(ConveyorServer::new(state))
+                .add_service(health)
+                .add_service(reflection(service))
                 .serve(addr)
-                .await?;
+                .await
         }
         Unit::InputStack => {
-            let stack = InputStack::new(name, 10);
+            let stack = InputStack::new(&name, paper_count);
+            let mut state = InputStackServerState::new(stack, delayer).with_faults(faults);
+            if let Some(exporter) = exporter {
+                state = state.with_exporter(exporter);
+            }
+            spawn_metrics(state.metrics(), metrics_port);
+            let service = InputStackServer::<InputStackServerState>::NAME;
+            let health = health(state.health(), service);
             Server::builder()
-                .add_service(InputStackServer::new(InputStackServerState::new(
-                    stack, delayer,
-                )))
+                .add_service(InputStackServer::new(state))
+                .add_service(health)
+                .add_service(reflection(service))
                 .serve(addr)
-                .await?;
+                .await
         }
         Unit::OutputStack => {
-            let stack = OutputStack::new(name);
+            let stack = OutputStack::new(&name);
+            let mut state = OutputStackServerState::new(stack, delayer).with_faults(faults);
+            if let Some(exporter) = exporter {
+                state = state.with_exporter(exporter);
+            }
+            spawn_metrics(state.metrics(), metrics_port);
+            let service = OutputStackServer::<OutputStackServerState>::NAME;
+            let health = health(state.health(), service);
             Server::builder()
-                .add_service(OutputStackServer::new(OutputStackServerState::new(
-                    stack, delayer,
-                )))
+                .add_service(OutputStackServer::new(state))
+                .add_service(health)
+                .add_service(reflection(service))
                 .serve(addr)
-                .await?;
+                .await
         }
     }
-    Ok(())
+}
+
+/// Serves the metrics in the background, logging when the server fails
+fn spawn_metrics(metrics: Arc<Metrics>, port: Option<u16>) {
+    if let Some(port) = port {
+        let addr = ([0, 0, 0, 0], port).into();
+        info!(address = %addr, "Serving metrics");
+        tokio::spawn(async move {
+            if let Err(e) = serve_metrics(metrics, addr).await {
+                error!(error = %e, "Metrics server failed");
+            }
+        });
+    }
+}
+
+/// Reflection service describing the unit served as `service` and the
+/// services next to it
+fn reflection(service: &'static str) -> ServerReflectionServer<ReflectionServerState> {
+    ServerReflectionServer::new(ReflectionServerState::new(&[
+        service,
+        HealthServer::<HealthServerState>::NAME,
+        ServerReflectionServer::<ReflectionServerState>::NAME,
+    ]))
+}
+
+/// Health service reporting on the unit served as `service`
+fn health(health: Arc<Health>, service: &'static str) -> HealthServer<HealthServerState> {
+    HealthServer::new(HealthServerState::new(health, service))
 }
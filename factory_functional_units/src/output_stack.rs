@@ -1,13 +1,15 @@
+use crate::Sheet;
+
 pub struct OutputStack {
     name: String,
-    paper_count: u32,
+    sheets: Vec<Sheet>,
 }
 
 impl OutputStack {
     pub fn new(name: &str) -> OutputStack {
         OutputStack {
             name: String::from(name),
-            paper_count: 0,
+            sheets: Vec::new(),
         }
     }
 
@@ -16,11 +18,21 @@ impl OutputStack {
     }
 
     pub fn paper_count(&self) -> u32 {
-        self.paper_count
+        self.sheets.len() as u32
+    }
+
+    /// Finished sheets, in the order they arrived
+    pub fn sheets(&self) -> &[Sheet] {
+        &self.sheets
+    }
+
+    pub fn pull(&mut self, sheet: Sheet) {
+        self.sheets.push(sheet);
     }
 
-    pub fn pull(&mut self) {
-        self.paper_count += 1;
+    /// Takes all sheets off the stack
+    pub fn clear(&mut self) -> Vec<Sheet> {
+        std::mem::take(&mut self.sheets)
     }
 }
 
@@ -40,10 +52,14 @@ mod tests {
     fn pull() {
         let mut stack = OutputStack::new("Main");
 
-        stack.pull();
+        stack.pull(Sheet::new("Main-1"));
         assert_eq!(1, stack.paper_count());
 
-        stack.pull();
+        stack.pull(Sheet::new("Main-2"));
         assert_eq!(2, stack.paper_count());
+        assert_eq!(
+            &[Sheet::new("Main-1"), Sheet::new("Main-2")],
+            stack.sheets()
+        );
     }
 }
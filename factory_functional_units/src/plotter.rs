@@ -1,20 +1,29 @@
-use crate::PushOrPullError;
+use crate::{PlotterFunction, PushOrPullError, Sheet, Usage};
 
 pub struct Plotter {
     name: String,
-    has_paper: bool,
+    functions: Vec<PlotterFunction>,
+    sheet: Option<Sheet>,
+    plots: u64,
+    sheets_moved: u64,
 }
 
 #[derive(PartialEq, Debug)]
 pub enum PlotError {
     NoPaper,
+    UnsupportedFunction,
+    /// The pen failed, the sheet was not plotted on
+    PenFailure,
 }
 
 impl Plotter {
-    pub fn new(name: &str) -> Plotter {
+    pub fn new(name: &str, functions: &[PlotterFunction]) -> Plotter {
         Plotter {
             name: String::from(name),
-            has_paper: false,
+            functions: functions.to_vec(),
+            sheet: None,
+            plots: 0,
+            sheets_moved: 0,
         }
     }
 
@@ -22,85 +31,155 @@ impl Plotter {
         &self.name
     }
 
+    pub fn functions(&self) -> &[PlotterFunction] {
+        &self.functions
+    }
+
     pub fn has_paper(&self) -> bool {
-        self.has_paper
+        self.sheet.is_some()
+    }
+
+    pub fn sheet(&self) -> Option<&Sheet> {
+        self.sheet.as_ref()
     }
 
-    pub fn plot(&self) -> Result<(), PlotError> {
-        if !self.has_paper {
+    /// Checks whether the plotter can apply the function, without applying
+    /// it
+    pub fn can_plot(&self, function: PlotterFunction) -> Result<(), PlotError> {
+        if !self.functions.contains(&function) {
+            Err(PlotError::UnsupportedFunction)
+        } else if self.sheet.is_none() {
             Err(PlotError::NoPaper)
         } else {
             Ok(())
         }
     }
 
-    pub fn push(&mut self) -> Result<(), PushOrPullError> {
-        if !self.has_paper {
-            Err(PushOrPullError::Empty)
-        } else {
-            self.has_paper = false;
-            Ok(())
+    /// Applies the function to the sheet held by the plotter
+    pub fn plot(&mut self, function: PlotterFunction) -> Result<(), PlotError> {
+        self.can_plot(function)?;
+        if let Some(sheet) = &mut self.sheet {
+            sheet.stamp(function);
+            self.plots += 1;
         }
+        Ok(())
+    }
+
+    pub fn push(&mut self) -> Result<Sheet, PushOrPullError> {
+        let sheet = self.sheet.take().ok_or(PushOrPullError::Empty)?;
+        self.sheets_moved += 1;
+        Ok(sheet)
     }
 
-    pub fn pull(&mut self) -> Result<(), PushOrPullError> {
-        if self.has_paper {
+    pub fn pull(&mut self, sheet: Sheet) -> Result<(), PushOrPullError> {
+        if self.sheet.is_some() {
             Err(PushOrPullError::Full)
         } else {
-            self.has_paper = true;
+            self.sheet = Some(sheet);
             Ok(())
         }
     }
+
+    /// Takes the sheet off the unit
+    pub fn clear(&mut self) -> Option<Sheet> {
+        self.sheet.take()
+    }
+
+    /// Plots and sheets moved over the plotter's lifetime, without uptime
+    pub fn usage(&self) -> Usage {
+        Usage {
+            plots: self.plots,
+            sheets_moved: self.sheets_moved,
+            ..Usage::default()
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn plotter() -> Plotter {
+        Plotter::new("Plotter 1", &[PlotterFunction::DrawRed])
+    }
+
     #[test]
     fn new() {
         let name = "Plotter 1";
-        let plot = Plotter::new(name);
+        let plot = Plotter::new(name, &[PlotterFunction::DrawRed]);
         assert_eq!(name, plot.name());
+        assert_eq!(&[PlotterFunction::DrawRed], plot.functions());
     }
 
     #[test]
     fn pull_and_push() -> Result<(), PushOrPullError> {
-        let mut plot = Plotter::new("Plotter 1");
-        plot.pull()?;
-        assert_eq!(true, plot.has_paper);
+        let mut plot = plotter();
+        plot.pull(Sheet::new("Main-1"))?;
+        assert!(plot.has_paper());
+        assert_eq!(Some(&Sheet::new("Main-1")), plot.sheet());
 
-        plot.push()?;
-        assert_eq!(false, plot.has_paper);
+        assert_eq!(Sheet::new("Main-1"), plot.push()?);
+        assert!(!plot.has_paper());
 
         Ok(())
     }
 
     #[test]
     fn empty_push() {
-        let mut plot = Plotter::new("Plotter 1");
+        let mut plot = plotter();
         assert_eq!(Err(PushOrPullError::Empty), plot.push());
     }
 
     #[test]
     fn full_pull() {
-        let mut plot = Plotter::new("Plotter 1");
-        assert_eq!(Ok(()), plot.pull());
+        let mut plot = plotter();
+        assert_eq!(Ok(()), plot.pull(Sheet::new("Main-1")));
 
-        assert_eq!(Err(PushOrPullError::Full), plot.pull());
+        assert_eq!(Err(PushOrPullError::Full), plot.pull(Sheet::new("Main-2")));
+        assert_eq!(Some(&Sheet::new("Main-1")), plot.sheet());
     }
 
     #[test]
     fn plot() {
-        let mut plot = Plotter::new("Plotter 1");
-        assert_eq!(Ok(()), plot.pull());
+        let mut plot = plotter();
+        assert_eq!(Ok(()), plot.pull(Sheet::new("Main-1")));
+
+        assert_eq!(Ok(()), plot.plot(PlotterFunction::DrawRed));
+        assert_eq!(
+            &[PlotterFunction::DrawRed],
+            plot.sheet().unwrap().functions()
+        );
+    }
 
-        assert_eq!(Ok(()), plot.plot());
+    #[test]
+    fn usage() {
+        let mut plot = plotter();
+        assert_eq!(Err(PlotError::NoPaper), plot.plot(PlotterFunction::DrawRed));
+        assert_eq!(Ok(()), plot.pull(Sheet::new("Main-1")));
+        assert_eq!(Ok(()), plot.plot(PlotterFunction::DrawRed));
+        assert!(plot.push().is_ok());
+        assert!(plot.push().is_err());
+
+        let usage = plot.usage();
+        assert_eq!(1, usage.plots);
+        assert_eq!(1, usage.sheets_moved);
     }
 
     #[test]
     fn empty_plot() {
-        let plot = Plotter::new("Plotter 1");
-        assert_eq!(Err(PlotError::NoPaper), plot.plot());
+        let mut plot = plotter();
+        assert_eq!(Err(PlotError::NoPaper), plot.plot(PlotterFunction::DrawRed));
+    }
+
+    #[test]
+    fn unsupported_plot() {
+        let mut plot = plotter();
+        assert_eq!(Ok(()), plot.pull(Sheet::new("Main-1")));
+
+        assert_eq!(
+            Err(PlotError::UnsupportedFunction),
+            plot.plot(PlotterFunction::DrawBlue)
+        );
+        assert!(plot.sheet().unwrap().functions().is_empty());
     }
 }
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tonic::Request;
+
+/// Events kept by a unit before the oldest are dropped
+const CAPACITY: usize = 10_000;
+
+/// Who made a call
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Caller {
+    pub peer: Option<SocketAddr>,
+    /// Name sent in the `caller` metadata, empty if none
+    pub name: String,
+}
+
+impl Caller {
+    pub fn of<T>(req: &Request<T>) -> Caller {
+        Caller {
+            peer: req.remote_addr(),
+            name: req
+                .metadata()
+                .get("caller")
+                .and_then(|name| name.to_str().ok())
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Event {
+    pub time: SystemTime,
+    pub method: &'static str,
+    pub description: String,
+    pub result: String,
+    pub caller: Caller,
+}
+
+/// Calls that changed or tried to change the state of a unit, oldest first
+pub struct EventLog {
+    events: Mutex<VecDeque<Event>>,
+    capacity: usize,
+}
+
+impl EventLog {
+    pub fn new() -> EventLog {
+        EventLog::with_capacity(CAPACITY)
+    }
+
+    fn with_capacity(capacity: usize) -> EventLog {
+        EventLog {
+            events: Mutex::new(VecDeque::new()),
+            capacity,
+        }
+    }
+
+    /// Adds an event that happened at `time` on the unit's clock
+    pub fn record(
+        &self,
+        time: SystemTime,
+        method: &'static str,
+        caller: Caller,
+        description: String,
+        result: String,
+    ) {
+        let mut events = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(Event {
+            time,
+            method,
+            description,
+            result,
+            caller,
+        });
+    }
+
+    /// Events from `since` up to `until`, only the latest `limit` of them
+    /// unless it is 0
+    pub fn query(
+        &self,
+        since: Option<SystemTime>,
+        until: Option<SystemTime>,
+        limit: usize,
+    ) -> Vec<Event> {
+        let events = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        let since = since.unwrap_or(UNIX_EPOCH);
+        let mut matching: Vec<_> = events.iter().filter(|e| e.time >= since).cloned().collect();
+        if let Some(until) = until {
+            matching.retain(|e| e.time < until);
+        }
+        if limit > 0 && matching.len() > limit {
+            matching.drain(..matching.len() - limit);
+        }
+        matching
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        EventLog::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn record(log: &EventLog, description: &str) {
+        log.record(
+            SystemTime::now(),
+            "Push",
+            Caller::default(),
+            description.to_owned(),
+            "Ok".to_owned(),
+        );
+    }
+
+    fn descriptions(events: Vec<Event>) -> Vec<String> {
+        events.into_iter().map(|e| e.description).collect()
+    }
+
+    #[test]
+    fn query() {
+        let log = EventLog::new();
+        record(&log, "first");
+        std::thread::sleep(Duration::from_millis(5));
+        let between = SystemTime::now();
+        record(&log, "second");
+        record(&log, "third");
+
+        assert_eq!(
+            vec!["first", "second", "third"],
+            descriptions(log.query(None, None, 0))
+        );
+        assert_eq!(
+            vec!["second", "third"],
+            descriptions(log.query(Some(between), None, 0))
+        );
+        assert_eq!(
+            vec!["first"],
+            descriptions(log.query(None, Some(between), 0))
+        );
+        assert_eq!(vec!["third"], descriptions(log.query(None, None, 1)));
+    }
+
+    #[test]
+    fn capacity() {
+        let log = EventLog::with_capacity(2);
+        for description in &["first", "second", "third"] {
+            record(&log, description);
+        }
+        assert_eq!(
+            vec!["second", "third"],
+            descriptions(log.query(None, None, 0))
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, watch};
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+use super::forward;
+use super::health_proto::health_check_response::ServingStatus;
+use super::health_proto::health_server;
+use super::health_proto::{HealthCheckRequest, HealthCheckResponse};
+
+pub use super::health_proto::health_server::HealthServer;
+
+/// Whether a unit is able to take calls
+#[derive(PartialEq, Debug, Clone)]
+pub enum Condition {
+    Operational,
+    /// The unit failed and needs to be looked at, with the reason
+    Faulted(String),
+    /// The unit is taken off the floor on purpose
+    Maintenance,
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Operational => write!(f, "operational"),
+            Condition::Faulted(reason) => write!(f, "faulted: {}", reason),
+            Condition::Maintenance => write!(f, "in maintenance"),
+        }
+    }
+}
+
+/// Condition of a unit shared between its service and the health service
+pub struct Health {
+    updates: watch::Sender<Condition>,
+    watch: watch::Receiver<Condition>,
+}
+
+impl Health {
+    pub fn new() -> Health {
+        let (updates, watch) = watch::channel(Condition::Operational);
+        Health { updates, watch }
+    }
+
+    pub fn condition(&self) -> Condition {
+        self.watch.borrow().clone()
+    }
+
+    pub fn set(&self, condition: Condition) {
+        info!(condition = %condition, "Condition changed");
+        // The health state keeps a receiver, so there always is one
+        let _ = self.updates.broadcast(condition);
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health::new()
+    }
+}
+
+fn serving_status(condition: &Condition) -> ServingStatus {
+    match condition {
+        Condition::Operational => ServingStatus::Serving,
+        _ => ServingStatus::NotServing,
+    }
+}
+
+/// Implements the gRPC health checking protocol for a single unit.
+///
+/// Besides the unit's own service, the empty service name asks for the
+/// health of the whole server, which is the same here.
+pub struct HealthServerState {
+    health: Arc<Health>,
+    service: &'static str,
+}
+
+impl HealthServerState {
+    /// `service` is the full name of the unit's gRPC service
+    pub fn new(health: Arc<Health>, service: &'static str) -> HealthServerState {
+        HealthServerState { health, service }
+    }
+
+    fn knows(&self, service: &str) -> bool {
+        service.is_empty() || service == self.service
+    }
+}
+
+#[tonic::async_trait]
+impl health_server::Health for HealthServerState {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        let service = request.into_inner().service;
+        if !self.knows(&service) {
+            return Err(Status::not_found(format!("Unknown service '{}'", service)));
+        }
+        Ok(Response::new(HealthCheckResponse {
+            status: serving_status(&self.health.condition()) as i32,
+        }))
+    }
+
+    type WatchStream = mpsc::Receiver<Result<HealthCheckResponse, Status>>;
+
+    async fn watch(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let known = self.knows(&request.into_inner().service);
+        Ok(Response::new(forward(
+            self.health.watch.clone(),
+            move |condition| HealthCheckResponse {
+                status: if known {
+                    serving_status(condition) as i32
+                } else {
+                    ServingStatus::ServiceUnknown as i32
+                },
+            },
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::health_server::Health as _;
+    use super::*;
+
+    fn request(service: &str) -> Request<HealthCheckRequest> {
+        Request::new(HealthCheckRequest {
+            service: service.to_owned(),
+        })
+    }
+
+    #[tokio::test]
+    async fn check() {
+        let health = Arc::new(Health::new());
+        let state = HealthServerState::new(health.clone(), "functional_units.Plotter");
+        let status = |r: Response<HealthCheckResponse>| r.into_inner().status;
+
+        let serving = state.check(request("")).await.unwrap();
+        assert_eq!(ServingStatus::Serving as i32, status(serving));
+
+        health.set(Condition::Maintenance);
+        let maintained = state
+            .check(request("functional_units.Plotter"))
+            .await
+            .unwrap();
+        assert_eq!(ServingStatus::NotServing as i32, status(maintained));
+
+        let unknown = state.check(request("functional_units.Conveyor")).await;
+        assert_eq!(tonic::Code::NotFound, unknown.unwrap_err().code());
+    }
+
+    #[tokio::test]
+    async fn watch() {
+        let health = Arc::new(Health::new());
+        let state = HealthServerState::new(health.clone(), "functional_units.Plotter");
+        let mut stream = state.watch(request("")).await.unwrap().into_inner();
+        let first = stream.recv().await.unwrap().unwrap();
+        assert_eq!(ServingStatus::Serving as i32, first.status);
+
+        health.set(Condition::Faulted("Pen broke".to_owned()));
+        let second = stream.recv().await.unwrap().unwrap();
+        assert_eq!(ServingStatus::NotServing as i32, second.status);
+    }
+}
@@ -0,0 +1,266 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Once};
+use std::time::Duration;
+
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+use crate::timing::Action;
+use crate::PlotterFunction;
+
+/// Numbers on the calls of a unit, exposed in the Prometheus text format
+pub struct Metrics {
+    registry: Registry,
+    /// Calls by method and result code
+    calls: IntCounterVec,
+    latencies: HistogramVec,
+    delays: HistogramVec,
+    /// Successful plots by function
+    plots: IntCounterVec,
+    paper_count: IntGauge,
+    /// Registers the paper count with the first count, so only stacks have one
+    paper_count_registered: Once,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let calls = IntCounterVec::new(
+            Opts::new("fiab_calls_total", "Calls by method and result code."),
+            &["method", "code"],
+        )
+        .expect("Valid metric");
+        let latencies = HistogramVec::new(
+            HistogramOpts::new("fiab_call_duration_seconds", "Time taken to answer a call."),
+            &["method"],
+        )
+        .expect("Valid metric");
+        let delays = HistogramVec::new(
+            HistogramOpts::new("fiab_delay_seconds", "Simulated duration of operations."),
+            &["action"],
+        )
+        .expect("Valid metric");
+        let plots = IntCounterVec::new(
+            Opts::new("fiab_plots_total", "Sheets plotted by function."),
+            &["function"],
+        )
+        .expect("Valid metric");
+        let paper_count =
+            IntGauge::new("fiab_paper_count", "Sheets on the stack.").expect("Valid metric");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(calls.clone()))
+            .and_then(|_| registry.register(Box::new(latencies.clone())))
+            .and_then(|_| registry.register(Box::new(delays.clone())))
+            .and_then(|_| registry.register(Box::new(plots.clone())))
+            .expect("Metric names are unique");
+        Metrics {
+            registry,
+            calls,
+            latencies,
+            delays,
+            plots,
+            paper_count,
+            paper_count_registered: Once::new(),
+        }
+    }
+
+    /// Counts a call ending with `code`, which took `latency` to answer
+    pub(crate) fn observe_call(&self, method: &'static str, code: String, latency: Duration) {
+        self.calls.with_label_values(&[method, &code]).inc();
+        self.latencies
+            .with_label_values(&[method])
+            .observe(latency.as_secs_f64());
+    }
+
+    /// Records the simulated duration of an operation
+    pub(crate) fn observe_delay(&self, action: Action, delay: Duration) {
+        self.delays
+            .with_label_values(&[&format!("{:?}", action)])
+            .observe(delay.as_secs_f64());
+    }
+
+    pub(crate) fn count_plot(&self, function: PlotterFunction) {
+        self.plots
+            .with_label_values(&[&format!("{:?}", function)])
+            .inc();
+    }
+
+    /// Sheets on a stack, only reported by stacks
+    pub(crate) fn set_paper_count(&self, count: u32) {
+        self.paper_count_registered.call_once(|| {
+            self.registry
+                .register(Box::new(self.paper_count.clone()))
+                .expect("Metric names are unique");
+        });
+        self.paper_count.set(count.into());
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut out)
+            .expect("Gathered metrics are valid");
+        String::from_utf8(out).expect("Metrics are UTF-8")
+    }
+}
+
+fn respond(metrics: &Metrics, req: &Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = StatusCode::NOT_FOUND;
+        return res;
+    }
+    let mut res = Response::new(Body::from(metrics.render()));
+    res.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    res
+}
+
+/// Serves the metrics of a unit over HTTP at `/metrics`
+pub async fn serve_metrics(metrics: Arc<Metrics>, addr: SocketAddr) -> Result<(), hyper::Error> {
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let res = respond(&metrics, &req);
+                async move { Ok::<_, Infallible>(res) }
+            }))
+        }
+    });
+    hyper::Server::bind(&addr).serve(make_service).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    /// Value of each sample by name and labels, read the way Prometheus
+    /// parses the text format
+    fn parse(out: &str) -> BTreeMap<(String, BTreeMap<String, String>), f64> {
+        let mut samples = BTreeMap::new();
+        for line in out.lines().filter(|l| !l.starts_with('#')) {
+            let (series, value) = line.split_at(line.rfind(' ').unwrap());
+            let (name, mut rest) = match series.find('{') {
+                Some(i) => (&series[..i], &series[i + 1..]),
+                None => (series, "}"),
+            };
+            let mut labels = BTreeMap::new();
+            while !rest.starts_with('}') {
+                let eq = rest.find("=\"").unwrap();
+                let key = rest[..eq].to_owned();
+                let mut value = String::new();
+                let mut chars = rest[eq + 2..].char_indices();
+                let end = loop {
+                    match chars.next().unwrap() {
+                        (i, '"') => break eq + 2 + i + 1,
+                        (_, '\\') => match chars.next().unwrap().1 {
+                            'n' => value.push('\n'),
+                            c => value.push(c),
+                        },
+                        (_, c) => value.push(c),
+                    }
+                };
+                labels.insert(key, value);
+                rest = rest[end..].trim_start_matches(',');
+            }
+            samples.insert((name.to_owned(), labels), value.trim().parse().unwrap());
+        }
+        samples
+    }
+
+    fn sample(
+        samples: &BTreeMap<(String, BTreeMap<String, String>), f64>,
+        name: &str,
+        labels: &[(&str, &str)],
+    ) -> Option<f64> {
+        let labels = labels
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect();
+        samples.get(&(name.to_owned(), labels)).copied()
+    }
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::new();
+        metrics.observe_call("Push", "Empty".to_owned(), Duration::from_millis(20));
+        metrics.observe_call("Push", "Ok".to_owned(), Duration::from_millis(300));
+        metrics.observe_call("Push", "Ok".to_owned(), Duration::from_secs(20));
+        metrics.observe_call(
+            "Push",
+            "Odd \"code\"\\\n".to_owned(),
+            Duration::from_millis(1),
+        );
+        metrics.observe_delay(Action::Push, Duration::from_millis(150));
+        metrics.count_plot(PlotterFunction::DrawRed);
+        let out = metrics.render();
+        let samples = parse(&out);
+
+        let calls = |code| {
+            sample(
+                &samples,
+                "fiab_calls_total",
+                &[("method", "Push"), ("code", code)],
+            )
+        };
+        assert_eq!(Some(1.0), calls("Empty"));
+        assert_eq!(Some(2.0), calls("Ok"));
+        // Label values are escaped
+        assert_eq!(Some(1.0), calls("Odd \"code\"\\\n"));
+        let bucket = |le| {
+            sample(
+                &samples,
+                "fiab_call_duration_seconds_bucket",
+                &[("method", "Push"), ("le", le)],
+            )
+        };
+        assert_eq!(Some(2.0), bucket("0.025"));
+        assert_eq!(Some(3.0), bucket("10"));
+        assert_eq!(Some(4.0), bucket("+Inf"));
+        assert_eq!(
+            Some(4.0),
+            sample(
+                &samples,
+                "fiab_call_duration_seconds_count",
+                &[("method", "Push")]
+            )
+        );
+        assert_eq!(
+            Some(1.0),
+            sample(
+                &samples,
+                "fiab_delay_seconds_bucket",
+                &[("action", "Push"), ("le", "0.25")]
+            )
+        );
+        assert_eq!(
+            Some(1.0),
+            sample(&samples, "fiab_plots_total", &[("function", "DrawRed")])
+        );
+        // Only stacks have a paper count
+        assert!(!out.contains("fiab_paper_count"));
+        metrics.set_paper_count(7);
+        metrics.set_paper_count(6);
+        assert_eq!(
+            Some(6.0),
+            sample(&parse(&metrics.render()), "fiab_paper_count", &[])
+        );
+    }
+}
@@ -1,23 +1,240 @@
-use std::sync::Mutex;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use rand::Rng;
-use tonic::{Request, Response, Status};
+use prost::Message;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tokio::sync::{mpsc, watch};
+use tonic::{Code, Request, Response, Status};
+use tracing::{debug, info, info_span, warn, Span};
+use tracing_futures::Instrument;
 
 pub use functional_units::conveyor_server::ConveyorServer;
 pub use functional_units::input_stack_server::InputStackServer;
 pub use functional_units::output_stack_server::OutputStackServer;
 pub use functional_units::plotter_server::PlotterServer;
+pub use health::{Condition, Health, HealthServer, HealthServerState};
+pub use metrics::{serve_metrics, Metrics};
+pub use reflection::{ReflectionServerState, ServerReflectionServer};
 
-use crate::{Conveyor, InputStack, Orientation, OutputStack, PlotError, PushOrPullError};
+use crate::timing::{Action, Clock, Distribution, Timing};
+use crate::trace::{SpanExporter, SpanKind, TraceContext, TraceSpan};
+use crate::wear::Wear;
+use crate::{
+    Conveyor, Fault, Faults, InputStack, Orientation, OutputStack, PlotError, PlotterFunction,
+    PushOrPullError, Sheet, TurnError, Usage, WearModel,
+};
+use event_log::{Caller, EventLog};
 
 use super::Plotter;
 
-mod functional_units;
+mod event_log;
+pub(crate) mod functional_units;
+mod health;
+#[path = "grpc.health.v1.rs"]
+pub(crate) mod health_proto;
+mod metrics;
+mod reflection;
+#[path = "grpc.reflection.v1alpha.rs"]
+mod reflection_proto;
 
-impl Into<i32> for &Orientation {
-    fn into(self) -> i32 {
+/// Units leave their state consistent after every call, so the state of a
+/// handler that panicked while holding the lock is still safe to use. The
+/// unit is reported as faulted though, as the panic needs to be looked at.
+fn lock<'a, T>(state: &'a Mutex<T>, health: &Health) -> MutexGuard<'a, T> {
+    state.lock().unwrap_or_else(|e| {
+        warn!("Recovering state after a panic in another call");
+        if let Condition::Operational = health.condition() {
+            health.set(Condition::Faulted("A call panicked".to_owned()));
+        }
+        PoisonError::into_inner(e)
+    })
+}
+
+/// Span of a call, naming the unit and its type so the logs of units hosted
+/// together can be told apart, and the trace the call is part of
+fn call_span(kind: &'static str, unit: &str, method: &'static str, trace: &TraceSpan) -> Span {
+    info_span!(
+        "call",
+        kind = kind,
+        unit = unit,
+        method = method,
+        trace_id = %trace.context().trace_id()
+    )
+}
+
+/// Trace span of a call, named after the gRPC method
+fn server_span(
+    kind: &'static str,
+    method: &'static str,
+    parent: Option<TraceContext>,
+    start: SystemTime,
+) -> TraceSpan {
+    let name = format!("functional_units.{}/{}", kind, method);
+    let mut trace = TraceSpan::start_at(&name, SpanKind::Server, parent, start);
+    trace.set_attribute("rpc.system", "grpc".to_owned());
+    trace.set_attribute("rpc.service", format!("functional_units.{}", kind));
+    trace.set_attribute("rpc.method", method.to_owned());
+    trace
+}
+
+/// Result code of a call's reply, counted in the metrics
+trait CallResult {
+    fn code(&self) -> String {
+        "Ok".to_owned()
+    }
+}
+
+/// Name of a known result code, the value itself otherwise
+fn code_name<C: std::fmt::Debug>(code: Option<C>, value: i32) -> String {
+    match code {
+        Some(code) => format!("{:?}", code),
+        None => value.to_string(),
+    }
+}
+
+impl CallResult for () {}
+impl CallResult for functional_units::PlotterStatus {}
+impl CallResult for functional_units::ConveyorStatus {}
+impl CallResult for functional_units::InputStackStatus {}
+impl CallResult for functional_units::OutputStackStatus {}
+impl CallResult for functional_units::RemovedSheets {}
+impl CallResult for functional_units::History {}
+impl<T> CallResult for mpsc::Receiver<T> {}
+
+impl CallResult for functional_units::PlotResult {
+    fn code(&self) -> String {
+        code_name(
+            functional_units::plot_result::Code::from_i32(self.code),
+            self.code,
+        )
+    }
+}
+
+impl CallResult for functional_units::PushOrPullResult {
+    fn code(&self) -> String {
+        code_name(
+            functional_units::push_or_pull_result::Code::from_i32(self.code),
+            self.code,
+        )
+    }
+}
+
+impl CallResult for functional_units::TurnToResult {
+    fn code(&self) -> String {
+        code_name(
+            functional_units::turn_to_result::Code::from_i32(self.code),
+            self.code,
+        )
+    }
+}
+
+/// Counts the call with its result code and latency, rejected calls by their
+/// gRPC status code
+async fn observe<T, F>(
+    metrics: &Metrics,
+    method: &'static str,
+    call: F,
+) -> Result<Response<T>, Status>
+where
+    T: CallResult,
+    F: Future<Output = Result<Response<T>, Status>>,
+{
+    let start = Instant::now();
+    let res = call.await;
+    metrics.observe_call(method, result_code(&res), start.elapsed());
+    res
+}
+
+/// Result code of the reply, or the gRPC status code of a rejected call
+fn result_code<T: CallResult>(res: &Result<Response<T>, Status>) -> String {
+    match res {
+        Ok(reply) => reply.get_ref().code(),
+        Err(status) => format!("{:?}", status.code()),
+    }
+}
+
+/// Ends the trace span of a call with its result
+fn export<T: CallResult>(
+    exporter: &SpanExporter,
+    mut trace: TraceSpan,
+    unit: &str,
+    res: &Result<Response<T>, Status>,
+    end: SystemTime,
+) {
+    trace.set_attribute("unit", unit.to_owned());
+    trace.set_attribute("result", result_code(res));
+    exporter.export_at(trace, end);
+}
+
+fn error_info(reason: &str, metadata: &[(&str, String)]) -> functional_units::ErrorInfo {
+    functional_units::ErrorInfo {
+        reason: reason.to_owned(),
+        metadata: metadata
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), v.clone()))
+            .collect::<HashMap<_, _>>(),
+    }
+}
+
+/// Status with an [`ErrorInfo`](functional_units::ErrorInfo) as details
+pub(crate) fn status_with_info(
+    code: Code,
+    message: &str,
+    reason: &str,
+    metadata: &[(&str, String)],
+) -> Status {
+    let mut details = Vec::new();
+    error_info(reason, metadata)
+        .encode(&mut details)
+        .expect("Vec grows as needed");
+    Status::with_details(code, message, details.into())
+}
+
+fn unknown_function(value: i32) -> Status {
+    status_with_info(
+        Code::InvalidArgument,
+        "Unknown plotter function",
+        "UNKNOWN_FUNCTION",
+        &[("value", value.to_string())],
+    )
+}
+
+impl PushOrPullError {
+    fn reason(&self) -> &'static str {
+        match self {
+            PushOrPullError::Empty => "UNIT_EMPTY",
+            PushOrPullError::Full => "UNIT_FULL",
+            PushOrPullError::Jammed => "JAMMED",
+            PushOrPullError::Dropped => "SHEET_DROPPED",
+        }
+    }
+}
+
+impl PlotError {
+    fn reason(&self) -> &'static str {
         match self {
+            PlotError::NoPaper => "NO_PAPER",
+            PlotError::UnsupportedFunction => "UNSUPPORTED_FUNCTION",
+            PlotError::PenFailure => "PEN_FAILURE",
+        }
+    }
+}
+
+impl TurnError {
+    fn reason(&self) -> &'static str {
+        match self {
+            TurnError::Stalled => "STALLED",
+        }
+    }
+}
+
+impl From<&Orientation> for i32 {
+    fn from(o: &Orientation) -> Self {
+        match o {
             Orientation::North => functional_units::Orientation::North.into(),
             Orientation::East => functional_units::Orientation::East.into(),
             Orientation::South => functional_units::Orientation::South.into(),
@@ -26,9 +243,9 @@ impl Into<i32> for &Orientation {
     }
 }
 
-impl Into<Orientation> for functional_units::Orientation {
-    fn into(self) -> Orientation {
-        match self {
+impl From<functional_units::Orientation> for Orientation {
+    fn from(o: functional_units::Orientation) -> Self {
+        match o {
             functional_units::Orientation::North => Orientation::North,
             functional_units::Orientation::East => Orientation::East,
             functional_units::Orientation::South => Orientation::South,
@@ -37,274 +254,1523 @@ impl Into<Orientation> for functional_units::Orientation {
     }
 }
 
-impl Into<functional_units::PushOrPullResult> for Result<(), PushOrPullError> {
-    fn into(self) -> functional_units::PushOrPullResult {
-        functional_units::PushOrPullResult {
-            code: match self {
-                Ok(_) => functional_units::push_or_pull_result::Code::Ok.into(),
-                Err(e) => match e {
+impl From<PlotterFunction> for i32 {
+    fn from(f: PlotterFunction) -> Self {
+        match f {
+            PlotterFunction::DrawRed => functional_units::PlotterFunction::DrawRed.into(),
+            PlotterFunction::DrawGreen => functional_units::PlotterFunction::DrawGreen.into(),
+            PlotterFunction::DrawBlue => functional_units::PlotterFunction::DrawBlue.into(),
+            PlotterFunction::DrawYellow => functional_units::PlotterFunction::DrawYellow.into(),
+        }
+    }
+}
+
+impl From<functional_units::PlotterFunction> for PlotterFunction {
+    fn from(f: functional_units::PlotterFunction) -> Self {
+        match f {
+            functional_units::PlotterFunction::DrawRed => PlotterFunction::DrawRed,
+            functional_units::PlotterFunction::DrawGreen => PlotterFunction::DrawGreen,
+            functional_units::PlotterFunction::DrawBlue => PlotterFunction::DrawBlue,
+            functional_units::PlotterFunction::DrawYellow => PlotterFunction::DrawYellow,
+        }
+    }
+}
+
+impl From<&Sheet> for functional_units::Sheet {
+    fn from(s: &Sheet) -> Self {
+        functional_units::Sheet {
+            id: s.id().to_owned(),
+            functions: s.functions().iter().map(|&f| f.into()).collect(),
+        }
+    }
+}
+
+impl TryFrom<functional_units::Sheet> for Sheet {
+    /// Value of the first unknown plotter function
+    type Error = i32;
+
+    fn try_from(s: functional_units::Sheet) -> Result<Self, Self::Error> {
+        let mut functions = Vec::with_capacity(s.functions.len());
+        for &f in &s.functions {
+            functions.push(
+                functional_units::PlotterFunction::from_i32(f)
+                    .ok_or(f)?
+                    .into(),
+            );
+        }
+        Ok(Sheet::with_functions(&s.id, functions))
+    }
+}
+
+impl TryFrom<functional_units::PullRequest> for Sheet {
+    type Error = Status;
+
+    fn try_from(req: functional_units::PullRequest) -> Result<Self, Self::Error> {
+        req.sheet
+            .ok_or_else(|| {
+                status_with_info(
+                    Code::InvalidArgument,
+                    "No sheet handed over",
+                    "MISSING_SHEET",
+                    &[],
+                )
+            })?
+            .try_into()
+            .map_err(unknown_function)
+    }
+}
+
+impl From<Result<Sheet, PushOrPullError>> for functional_units::PushOrPullResult {
+    fn from(res: Result<Sheet, PushOrPullError>) -> Self {
+        match res {
+            Ok(sheet) => functional_units::PushOrPullResult {
+                code: functional_units::push_or_pull_result::Code::Ok.into(),
+                sheet: Some((&sheet).into()),
+                error: None,
+            },
+            Err(e) => Result::<(), _>::Err(e).into(),
+        }
+    }
+}
+
+impl From<Result<(), PushOrPullError>> for functional_units::PushOrPullResult {
+    fn from(res: Result<(), PushOrPullError>) -> Self {
+        match res {
+            Ok(_) => functional_units::PushOrPullResult {
+                code: functional_units::push_or_pull_result::Code::Ok.into(),
+                sheet: None,
+                error: None,
+            },
+            Err(e) => functional_units::PushOrPullResult {
+                code: match e {
                     PushOrPullError::Empty => {
                         functional_units::push_or_pull_result::Code::Empty.into()
                     }
                     PushOrPullError::Full => {
                         functional_units::push_or_pull_result::Code::Full.into()
                     }
+                    PushOrPullError::Jammed => {
+                        functional_units::push_or_pull_result::Code::Jammed.into()
+                    }
+                    PushOrPullError::Dropped => {
+                        functional_units::push_or_pull_result::Code::Dropped.into()
+                    }
                 },
+                sheet: None,
+                error: Some(error_info(e.reason(), &[])),
             },
         }
     }
 }
 
-impl Into<functional_units::PlotResult> for Result<(), PlotError> {
-    fn into(self) -> functional_units::PlotResult {
-        functional_units::PlotResult {
-            code: match self {
-                Ok(_) => functional_units::plot_result::Code::Ok.into(),
-                Err(e) => match e {
+impl From<Result<(), PlotError>> for functional_units::PlotResult {
+    fn from(res: Result<(), PlotError>) -> Self {
+        match res {
+            Ok(_) => functional_units::PlotResult {
+                code: functional_units::plot_result::Code::Ok.into(),
+                error: None,
+            },
+            Err(e) => functional_units::PlotResult {
+                code: match e {
                     PlotError::NoPaper => functional_units::plot_result::Code::NoPaper.into(),
+                    PlotError::UnsupportedFunction => {
+                        functional_units::plot_result::Code::UnsupportedFunction.into()
+                    }
+                    PlotError::PenFailure => functional_units::plot_result::Code::PenFailure.into(),
                 },
+                error: Some(error_info(e.reason(), &[])),
             },
         }
     }
 }
 
+impl From<Result<(), TurnError>> for functional_units::TurnToResult {
+    fn from(res: Result<(), TurnError>) -> Self {
+        match res {
+            Ok(_) => functional_units::TurnToResult {
+                code: functional_units::turn_to_result::Code::Ok.into(),
+                error: None,
+            },
+            Err(e) => functional_units::TurnToResult {
+                code: match e {
+                    TurnError::Stalled => functional_units::turn_to_result::Code::Stalled.into(),
+                },
+                error: Some(error_info(e.reason(), &[])),
+            },
+        }
+    }
+}
+
+impl From<&Usage> for functional_units::Usage {
+    fn from(u: &Usage) -> Self {
+        functional_units::Usage {
+            plots: u.plots,
+            turns: u.turns,
+            degrees_rotated: u.degrees_rotated,
+            sheets_moved: u.sheets_moved,
+            uptime_seconds: u.uptime.as_secs(),
+        }
+    }
+}
+
+/// Status without uptime or wear, which only the server state knows
+impl From<&Plotter> for functional_units::PlotterStatus {
+    fn from(p: &Plotter) -> Self {
+        functional_units::PlotterStatus {
+            name: p.name().to_owned(),
+            has_paper: p.has_paper(),
+            functions: p.functions().iter().map(|&f| f.into()).collect(),
+            sheet: p.sheet().map(Into::into),
+            usage: Some((&p.usage()).into()),
+            wear: 0.0,
+            maintenance_due: false,
+        }
+    }
+}
+
+impl From<&Conveyor> for functional_units::ConveyorStatus {
+    fn from(c: &Conveyor) -> Self {
+        functional_units::ConveyorStatus {
+            name: c.name().to_owned(),
+            has_paper: c.has_paper(),
+            orientation: c.orientation().into(),
+            sheet: c.sheet().map(Into::into),
+            usage: Some((&c.usage()).into()),
+            wear: 0.0,
+            maintenance_due: false,
+        }
+    }
+}
+
+impl From<&InputStack> for functional_units::InputStackStatus {
+    fn from(s: &InputStack) -> Self {
+        functional_units::InputStackStatus {
+            name: s.name().to_owned(),
+            paper_count: s.paper_count(),
+            returned: s.returned().iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<&OutputStack> for functional_units::OutputStackStatus {
+    fn from(s: &OutputStack) -> Self {
+        functional_units::OutputStackStatus {
+            name: s.name().to_owned(),
+            paper_count: s.paper_count(),
+            sheets: s.sheets().iter().map(Into::into).collect(),
+        }
+    }
+}
+
+fn removed_sheets<I: IntoIterator<Item = Sheet>>(sheets: I) -> functional_units::RemovedSheets {
+    functional_units::RemovedSheets {
+        sheets: sheets.into_iter().map(|s| (&s).into()).collect(),
+    }
+}
+
+/// Ids of the sheets taken off a unit, for its history
+fn sheet_ids<'a, I: IntoIterator<Item = &'a Sheet>>(sheets: I) -> String {
+    let ids: Vec<_> = sheets.into_iter().map(Sheet::id).collect();
+    if ids.is_empty() {
+        "no sheets".to_owned()
+    } else {
+        ids.join(", ")
+    }
+}
+
+/// Events of the log within the requested times, oldest first
+fn history(log: &EventLog, req: &functional_units::GetHistoryRequest) -> functional_units::History {
+    // Times before the epoch filter as much as the epoch itself
+    let time = |t: &Option<prost_types::Timestamp>| {
+        t.clone()
+            .map(|t| SystemTime::try_from(t).unwrap_or(UNIX_EPOCH))
+    };
+    let events = log.query(time(&req.since), time(&req.until), req.limit as usize);
+    functional_units::History {
+        events: events
+            .into_iter()
+            .map(|e| functional_units::HistoryEvent {
+                time: Some(e.time.into()),
+                method: e.method.to_owned(),
+                description: e.description,
+                result: e.result,
+                peer: e.caller.peer.map(|p| p.to_string()).unwrap_or_default(),
+                caller: e.caller.name,
+            })
+            .collect(),
+    }
+}
+
+/// Streams the current value, as returned by `map`, followed by every change
+fn forward<T, U, F>(mut watch: watch::Receiver<T>, map: F) -> mpsc::Receiver<Result<U, Status>>
+where
+    T: Clone + Send + Sync + 'static,
+    U: Clone + PartialEq + Send + 'static,
+    F: Fn(&T) -> U + Send + 'static,
+{
+    let (mut tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut last = map(&watch.borrow());
+        if tx.send(Ok(last.clone())).await.is_err() {
+            return;
+        }
+        while let Some(value) = watch.recv().await {
+            // Calls that fail leave the value as it was
+            let value = map(&value);
+            if value == last {
+                continue;
+            }
+            last = value.clone();
+            if tx.send(Ok(value)).await.is_err() {
+                // The watcher hung up
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Streams the current status of a unit followed by every change
+fn watch_status<T>(watch: watch::Receiver<T>) -> mpsc::Receiver<Result<T, Status>>
+where
+    T: Clone + PartialEq + Send + Sync + 'static,
+{
+    forward(watch, T::clone)
+}
+
+/// Makes calls take as long as the operations on the real line.
+///
+/// For repeatable simulations the delays can be drawn from a seeded random
+/// number generator and pass on a faster or a virtual [`Clock`].
 pub struct Delayer {
-    min: Duration,
-    max: Duration,
+    timing: Timing,
+    rng: Mutex<StdRng>,
+    clock: Clock,
+    started: Instant,
+    started_at: SystemTime,
+    /// Sum of all delays, i.e. the unit's virtual time
+    elapsed: Mutex<Duration>,
 }
 
 impl Delayer {
+    /// Draws every delay uniformly from `min` to `max`
     pub fn new(min: Duration, max: Duration) -> Delayer {
-        Delayer { min, max }
+        Delayer::with_timing(Timing::uniform(Distribution::Uniform {
+            min_ms: min.as_secs_f64() * 1000.0,
+            max_ms: max.as_secs_f64() * 1000.0,
+        }))
+    }
+
+    pub fn with_timing(timing: Timing) -> Delayer {
+        Delayer {
+            timing,
+            rng: Mutex::new(StdRng::from_entropy()),
+            clock: Clock::Real,
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            elapsed: Mutex::new(Duration::from_secs(0)),
+        }
+    }
+
+    /// Draws the same delays for the same sequence of calls on every run
+    pub fn seeded(mut self, seed: u64) -> Delayer {
+        self.rng = Mutex::new(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Clock) -> Delayer {
+        self.clock = clock;
+        self
+    }
+
+    /// Time spent in all delays so far
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Time the unit is up, in real time on a real clock and in virtual
+    /// time otherwise
+    pub fn uptime(&self) -> Duration {
+        match self.clock {
+            Clock::Real => self.started.elapsed(),
+            Clock::Scaled(_) | Clock::Virtual => self.elapsed(),
+        }
+    }
+
+    /// Time on the unit's clock, which only advances with the delays unless
+    /// it is real. The unit's history and spans are stamped with it.
+    pub fn now(&self) -> SystemTime {
+        match self.clock {
+            Clock::Real => SystemTime::now(),
+            Clock::Scaled(_) | Clock::Virtual => self.started_at + self.elapsed(),
+        }
+    }
+
+    /// Delays for a draw of the action and returns its duration
+    pub async fn delay(&self, action: Action) -> Duration {
+        self.delay_times(action, 1).await
+    }
+
+    /// Delays for `count` draws of the action, not at all for 0, and returns
+    /// their total duration
+    pub async fn delay_times(&self, action: Action, count: u32) -> Duration {
+        if count == 0 {
+            return Duration::from_secs(0);
+        }
+        let dur: Duration = {
+            let distribution = self.timing.of(action);
+            let mut rng = self.rng.lock().unwrap_or_else(PoisonError::into_inner);
+            (0..count).map(|_| distribution.sample(&mut *rng)).sum()
+        };
+        *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner) += dur;
+        let real = self.clock.real_time(dur);
+        debug!(delay = ?dur, real_time = ?real, "Sleeping");
+        if real > Duration::from_secs(0) {
+            tokio::time::delay_for(real).await;
+        }
+        dur
+    }
+}
+
+/// A unit as served over gRPC
+pub trait ServedUnit: Send + 'static {
+    /// Name of the unit's service within the `functional_units` package
+    const SERVICE: &'static str;
+    type Status: Clone + PartialEq + std::fmt::Debug + Send + Sync + 'static;
+
+    fn unit_name(&self) -> &str;
+
+    /// Usage the unit wears by, none for units that do not wear
+    fn counters(&self) -> Usage {
+        Usage::default()
+    }
+
+    /// Status of the unit, with its `usage` and `wear` as only the server
+    /// state knows them
+    fn status(&self, usage: &Usage, wear: f64) -> Self::Status;
+
+    /// Updates the metrics that follow the unit's state
+    fn update_metrics(&self, _metrics: &Metrics) {}
+
+    /// Status as sent to watchers, who only get changes of the unit. Leaves
+    /// out what changes on its own, like the uptime.
+    fn watched(status: Self::Status) -> Self::Status {
+        status
     }
+}
+
+impl ServedUnit for Plotter {
+    const SERVICE: &'static str = "Plotter";
+    type Status = functional_units::PlotterStatus;
+
+    fn unit_name(&self) -> &str {
+        self.name()
+    }
+
+    fn counters(&self) -> Usage {
+        self.usage()
+    }
+
+    fn status(&self, usage: &Usage, wear: f64) -> Self::Status {
+        functional_units::PlotterStatus {
+            usage: Some(usage.into()),
+            wear,
+            maintenance_due: wear >= 1.0,
+            ..self.into()
+        }
+    }
+
+    fn watched(mut status: Self::Status) -> Self::Status {
+        if let Some(usage) = &mut status.usage {
+            usage.uptime_seconds = 0;
+        }
+        status
+    }
+}
+
+impl ServedUnit for Conveyor {
+    const SERVICE: &'static str = "Conveyor";
+    type Status = functional_units::ConveyorStatus;
+
+    fn unit_name(&self) -> &str {
+        self.name()
+    }
+
+    fn counters(&self) -> Usage {
+        self.usage()
+    }
+
+    fn status(&self, usage: &Usage, wear: f64) -> Self::Status {
+        functional_units::ConveyorStatus {
+            usage: Some(usage.into()),
+            wear,
+            maintenance_due: wear >= 1.0,
+            ..self.into()
+        }
+    }
+
+    fn watched(mut status: Self::Status) -> Self::Status {
+        if let Some(usage) = &mut status.usage {
+            usage.uptime_seconds = 0;
+        }
+        status
+    }
+}
+
+impl ServedUnit for InputStack {
+    const SERVICE: &'static str = "InputStack";
+    type Status = functional_units::InputStackStatus;
 
-    pub async fn delay(&self) {
-        let dur = rand::thread_rng().gen_range(self.min, self.max);
-        println!("Sleeping for {:?}", dur);
-        tokio::time::delay_for(dur).await;
+    fn unit_name(&self) -> &str {
+        self.name()
     }
+
+    fn status(&self, _usage: &Usage, _wear: f64) -> Self::Status {
+        self.into()
+    }
+
+    fn update_metrics(&self, metrics: &Metrics) {
+        metrics.set_paper_count(self.paper_count());
+    }
+}
+
+impl ServedUnit for OutputStack {
+    const SERVICE: &'static str = "OutputStack";
+    type Status = functional_units::OutputStackStatus;
+
+    fn unit_name(&self) -> &str {
+        self.name()
+    }
+
+    fn status(&self, _usage: &Usage, _wear: f64) -> Self::Status {
+        self.into()
+    }
+
+    fn update_metrics(&self, metrics: &Metrics) {
+        metrics.set_paper_count(self.paper_count());
+    }
+}
+
+/// Status of `unit` with the usage and wear its `wear` adds
+fn status_with_wear<U: ServedUnit>(unit: &U, wear: &Wear, uptime: Duration) -> U::Status {
+    let usage = wear.usage(unit.counters(), uptime);
+    unit.status(&usage, wear.wear(&usage))
 }
 
-pub struct PlotterServerState {
-    state: Mutex<Plotter>,
+/// Serves a unit: holds its state and runs every call through the parts all
+/// units share, i.e. spans, metrics, history, faults, delays and updates
+pub struct UnitCore<U: ServedUnit> {
+    state: Mutex<U>,
+    name: String,
     delayer: Delayer,
+    updates: watch::Sender<U::Status>,
+    watch: watch::Receiver<U::Status>,
+    health: Arc<Health>,
+    metrics: Arc<Metrics>,
+    events: EventLog,
+    faults: Faults,
+    exporter: Option<Arc<SpanExporter>>,
+    wear: Wear,
 }
 
-impl PlotterServerState {
-    pub fn new(p: Plotter, delayer: Delayer) -> PlotterServerState {
-        PlotterServerState {
-            state: Mutex::new(p),
+pub type PlotterServerState = UnitCore<Plotter>;
+pub type ConveyorServerState = UnitCore<Conveyor>;
+pub type InputStackServerState = UnitCore<InputStack>;
+pub type OutputStackServerState = UnitCore<OutputStack>;
+
+impl<U: ServedUnit> UnitCore<U> {
+    pub fn new(unit: U, delayer: Delayer) -> UnitCore<U> {
+        let wear = Wear::new(WearModel::none());
+        let status = U::watched(status_with_wear(&unit, &wear, delayer.uptime()));
+        let (updates, watch) = watch::channel(status);
+        let metrics = Metrics::new();
+        unit.update_metrics(&metrics);
+        UnitCore {
+            name: unit.unit_name().to_owned(),
+            state: Mutex::new(unit),
             delayer,
+            updates,
+            watch,
+            health: Arc::new(Health::new()),
+            metrics: Arc::new(metrics),
+            events: EventLog::new(),
+            faults: Faults::none(),
+            exporter: None,
+            wear,
+        }
+    }
+
+    /// Injects the faults into the calls of the unit
+    pub fn with_faults(mut self, faults: Faults) -> UnitCore<U> {
+        self.faults = faults;
+        self
+    }
+
+    /// Exports a span for every call
+    pub fn with_exporter(mut self, exporter: Arc<SpanExporter>) -> UnitCore<U> {
+        self.exporter = Some(exporter);
+        self
+    }
+
+    /// Wears the unit down by its usage, making maintenance due in time.
+    /// Stacks count no usage and never wear.
+    pub fn with_wear(mut self, model: WearModel) -> UnitCore<U> {
+        self.wear = Wear::new(model);
+        self
+    }
+
+    /// Health of the unit reported through grpc.health.v1
+    pub fn health(&self) -> Arc<Health> {
+        self.health.clone()
+    }
+
+    /// Metrics of the unit's calls
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, U> {
+        lock(&self.state, &self.health)
+    }
+
+    /// Runs a call in its span, as part of the caller's trace if it sent
+    /// one, and counts it in the unit's metrics
+    async fn call<T, F>(
+        &self,
+        method: &'static str,
+        parent: Option<TraceContext>,
+        call: F,
+    ) -> Result<Response<T>, Status>
+    where
+        T: CallResult,
+        F: Future<Output = Result<Response<T>, Status>>,
+    {
+        let trace = server_span(U::SERVICE, method, parent, self.delayer.now());
+        let span = call_span(U::SERVICE, &self.name, method, &trace);
+        let res = observe(&self.metrics, method, call.instrument(span)).await;
+        if let Some(exporter) = &self.exporter {
+            export(exporter, trace, &self.name, &res, self.delayer.now());
+        }
+        res
+    }
+
+    /// Delays for `count` draws of the action and records them
+    async fn delay(&self, action: Action, count: u32) {
+        let delay = self.delayer.delay_times(action, count).await;
+        if count > 0 {
+            self.metrics.observe_delay(action, delay);
+        }
+    }
+
+    /// Adds the call to the unit's history
+    fn record<T: CallResult>(
+        &self,
+        method: &'static str,
+        caller: Caller,
+        description: String,
+        reply: &T,
+    ) {
+        self.events.record(
+            self.delayer.now(),
+            method,
+            caller,
+            description,
+            reply.code(),
+        );
+    }
+
+    /// Status of the unit with its usage and wear
+    fn status_of(&self, state: &U) -> U::Status {
+        status_with_wear(state, &self.wear, self.delayer.uptime())
+    }
+
+    fn publish(&self, state: &U) {
+        state.update_metrics(&self.metrics);
+        // Never fails, the state keeps a receiver itself
+        let _ = self.updates.broadcast(U::watched(self.status_of(state)));
+    }
+
+    /// Counts leaving maintenance as servicing the unit, starting its wear
+    /// at 0 again
+    fn end_maintenance(&self) {
+        if let Condition::Maintenance = self.health.condition() {
+            let state = self.lock();
+            let usage = self.wear.usage(state.counters(), self.delayer.uptime());
+            self.wear.serviced(usage);
+            self.publish(&state);
         }
     }
+
+    /// Puts the unit back into operation, out of maintenance or a fault
+    fn resume_operation(&self) {
+        self.end_maintenance();
+        self.health.set(Condition::Operational);
+    }
+
+    async fn serve_watch_status(
+        &self,
+        req: Request<()>,
+    ) -> Result<Response<mpsc::Receiver<Result<U::Status, Status>>>, Status> {
+        self.call(
+            "WatchStatus",
+            TraceContext::extract(req.metadata()),
+            async move {
+                info!("Watching status");
+                Ok(Response::new(watch_status(self.watch.clone())))
+            },
+        )
+        .await
+    }
+
+    async fn serve_status(&self, req: Request<()>) -> Result<Response<U::Status>, Status>
+    where
+        U::Status: CallResult,
+    {
+        self.call(
+            "Status",
+            TraceContext::extract(req.metadata()),
+            async move {
+                let reply = self.status_of(&self.lock());
+                debug!(status = ?reply, "Status");
+                Ok(Response::new(reply))
+            },
+        )
+        .await
+    }
+
+    /// Takes the unit off the floor or puts it back
+    async fn serve_set_maintenance(
+        &self,
+        req: Request<functional_units::SetMaintenanceRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.call(
+            "SetMaintenance",
+            TraceContext::extract(req.metadata()),
+            async move {
+                let enabled = req.get_ref().enabled;
+                let description = if enabled {
+                    self.health.set(Condition::Maintenance);
+                    "Start maintenance"
+                } else {
+                    self.resume_operation();
+                    "End maintenance"
+                };
+                self.record(
+                    "SetMaintenance",
+                    Caller::of(&req),
+                    description.to_owned(),
+                    &(),
+                );
+                Ok(Response::new(()))
+            },
+        )
+        .await
+    }
+
+    async fn serve_get_history(
+        &self,
+        req: Request<functional_units::GetHistoryRequest>,
+    ) -> Result<Response<functional_units::History>, Status> {
+        self.call(
+            "GetHistory",
+            TraceContext::extract(req.metadata()),
+            async move { Ok(Response::new(history(&self.events, req.get_ref()))) },
+        )
+        .await
+    }
+
+    /// Hands out the unit's sheet with `push`, unless a fault strikes
+    async fn serve_push<F>(
+        &self,
+        req: Request<()>,
+        push: F,
+    ) -> Result<Response<functional_units::PushOrPullResult>, Status>
+    where
+        F: FnOnce(&mut U) -> Result<Sheet, PushOrPullError> + Send,
+    {
+        self.call("Push", TraceContext::extract(req.metadata()), async move {
+            let caller = Caller::of(&req);
+            let res = {
+                let mut state = self.lock();
+                let res = self.faults.push(|| push(&mut state));
+                self.publish(&state);
+                res
+            };
+            self.delay(Action::Push, 1).await;
+            info!(result = ?res, "Pushed");
+            let description = match &res {
+                Ok(sheet) => format!("Push sheet {}", sheet.id()),
+                Err(_) => "Push".to_owned(),
+            };
+            let reply = res.into();
+            self.record("Push", caller, description, &reply);
+            Ok(Response::new(reply))
+        })
+        .await
+    }
+
+    /// Takes the sheet a neighbour pushed with `pull`, unless a fault strikes
+    async fn serve_pull<F>(
+        &self,
+        req: Request<functional_units::PullRequest>,
+        pull: F,
+    ) -> Result<Response<functional_units::PushOrPullResult>, Status>
+    where
+        F: FnOnce(&mut U, Sheet) -> Result<(), PushOrPullError> + Send,
+    {
+        self.call("Pull", TraceContext::extract(req.metadata()), async move {
+            let caller = Caller::of(&req);
+            let sheet = Sheet::try_from(req.into_inner())?;
+            let description = format!("Pull sheet {}", sheet.id());
+            let res = {
+                let mut state = self.lock();
+                let res = self.faults.pull(|| pull(&mut state, sheet));
+                self.publish(&state);
+                res
+            };
+            self.delay(Action::Pull, 1).await;
+            info!(result = ?res, "Pulled");
+            let reply = res.into();
+            self.record("Pull", caller, description, &reply);
+            Ok(Response::new(reply))
+        })
+        .await
+    }
+
+    /// Puts a sheet on the unit with `insert`, without any delay or fault
+    async fn serve_insert_paper<F>(
+        &self,
+        req: Request<functional_units::PullRequest>,
+        insert: F,
+    ) -> Result<Response<functional_units::PushOrPullResult>, Status>
+    where
+        F: FnOnce(&mut U, Sheet) -> Result<(), PushOrPullError> + Send,
+    {
+        self.call(
+            "InsertPaper",
+            TraceContext::extract(req.metadata()),
+            async move {
+                let caller = Caller::of(&req);
+                let sheet = Sheet::try_from(req.into_inner())?;
+                let description = format!("Insert sheet {}", sheet.id());
+                let res = {
+                    let mut state = self.lock();
+                    let res = insert(&mut state, sheet);
+                    self.publish(&state);
+                    res
+                };
+                info!(result = ?res, "Inserted paper");
+                let reply = res.into();
+                self.record("InsertPaper", caller, description, &reply);
+                Ok(Response::new(reply))
+            },
+        )
+        .await
+    }
+
+    /// Takes the sheets off the unit with `clear`
+    async fn serve_clear<F, S>(
+        &self,
+        method: &'static str,
+        req: Request<()>,
+        clear: F,
+    ) -> Result<Response<functional_units::RemovedSheets>, Status>
+    where
+        F: FnOnce(&mut U) -> S + Send,
+        S: IntoIterator<Item = Sheet>,
+    {
+        self.call(method, TraceContext::extract(req.metadata()), async move {
+            let caller = Caller::of(&req);
+            let sheets: Vec<Sheet> = {
+                let mut state = self.lock();
+                let sheets = clear(&mut state).into_iter().collect();
+                self.publish(&state);
+                sheets
+            };
+            info!(sheets = ?sheets, "Cleared");
+            let description = format!("Clear, removed {}", sheet_ids(&sheets));
+            let reply = removed_sheets(sheets);
+            self.record(method, caller, description, &reply);
+            Ok(Response::new(reply))
+        })
+        .await
+    }
+
+    /// Puts the unit back into the state it started in with `reset` and
+    /// into operation
+    async fn serve_reset<F, S>(&self, req: Request<()>, reset: F) -> Result<Response<()>, Status>
+    where
+        F: FnOnce(&mut U) -> S + Send,
+        S: IntoIterator<Item = Sheet>,
+    {
+        self.call("Reset", TraceContext::extract(req.metadata()), async move {
+            let removed: Vec<Sheet> = {
+                let mut state = self.lock();
+                let removed = reset(&mut state).into_iter().collect();
+                self.publish(&state);
+                removed
+            };
+            self.resume_operation();
+            info!(removed = ?removed, "Reset");
+            let description = format!("Reset, removed {}", sheet_ids(&removed));
+            self.record("Reset", Caller::of(&req), description, &());
+            Ok(Response::new(()))
+        })
+        .await
+    }
 }
 
 #[tonic::async_trait]
 impl functional_units::plotter_server::Plotter for PlotterServerState {
+    type WatchStatusStream = mpsc::Receiver<Result<functional_units::PlotterStatus, Status>>;
+
+    async fn watch_status(
+        &self,
+        req: Request<()>,
+    ) -> Result<Response<Self::WatchStatusStream>, Status> {
+        self.serve_watch_status(req).await
+    }
+
     async fn status(
         &self,
-        _: Request<()>,
+        req: Request<()>,
     ) -> Result<Response<functional_units::PlotterStatus>, Status> {
-        let state = self.state.lock().unwrap();
-        let reply = functional_units::PlotterStatus {
-            name: state.name().to_owned(),
-            has_paper: state.has_paper(),
-        };
-        println!("status - {:?}", reply);
-        Ok(Response::new(reply))
+        self.serve_status(req).await
     }
 
-    async fn plot(&self, _: Request<()>) -> Result<Response<functional_units::PlotResult>, Status> {
-        let res = {
-            let state = self.state.lock().unwrap();
-            state.plot()
-        };
-        self.delayer.delay().await;
-        println!("plot - {:?}", res);
-        Ok(Response::new(res.into()))
+    async fn plot(
+        &self,
+        req: Request<functional_units::PlotRequest>,
+    ) -> Result<Response<functional_units::PlotResult>, Status> {
+        self.call("Plot", TraceContext::extract(req.metadata()), async move {
+            let value = req.get_ref().function;
+            let function = functional_units::PlotterFunction::from_i32(value)
+                .ok_or_else(|| unknown_function(value))?;
+            let res = {
+                let mut state = self.lock();
+                // Only plots that would work can fail, so the pen failure
+                // neither hides the reason nor strikes in vain
+                let res = state.can_plot(function.into()).and_then(|_| {
+                    if self.faults.strikes(Fault::PenFailure) {
+                        Err(PlotError::PenFailure)
+                    } else {
+                        state.plot(function.into())
+                    }
+                });
+                self.publish(&state);
+                res
+            };
+            self.delay(Action::Plot, 1).await;
+            if res.is_ok() {
+                self.metrics.count_plot(function.into());
+            }
+            info!(function = ?function, result = ?res, "Plotted");
+            let description = format!("Plot {:?}", function);
+            let reply = res.into();
+            self.record("Plot", Caller::of(&req), description, &reply);
+            Ok(Response::new(reply))
+        })
+        .await
     }
 
     async fn pull(
         &self,
-        _: Request<()>,
+        req: Request<functional_units::PullRequest>,
     ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
-        let res = {
-            let mut state = self.state.lock().unwrap();
-            state.pull()
-        };
-        self.delayer.delay().await;
-        println!("pull - {:?}", res);
-        Ok(Response::new(res.into()))
+        self.serve_pull(req, Plotter::pull).await
     }
 
     async fn push(
         &self,
-        _: Request<()>,
+        req: Request<()>,
     ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
-        let res = {
-            let mut state = self.state.lock().unwrap();
-            state.push()
-        };
-        self.delayer.delay().await;
-        println!("push - {:?}", res);
-        Ok(Response::new(res.into()))
+        self.serve_push(req, Plotter::push).await
     }
-}
 
-pub struct ConveyorServerState {
-    state: Mutex<Conveyor>,
-    delayer: Delayer,
-}
+    async fn insert_paper(
+        &self,
+        req: Request<functional_units::PullRequest>,
+    ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
+        self.serve_insert_paper(req, Plotter::pull).await
+    }
 
-impl ConveyorServerState {
-    pub fn new(c: Conveyor, delayer: Delayer) -> ConveyorServerState {
-        ConveyorServerState {
-            state: Mutex::new(c),
-            delayer,
-        }
+    async fn clear_paper(
+        &self,
+        req: Request<()>,
+    ) -> Result<Response<functional_units::RemovedSheets>, Status> {
+        self.serve_clear("ClearPaper", req, Plotter::clear).await
+    }
+
+    async fn set_maintenance(
+        &self,
+        req: Request<functional_units::SetMaintenanceRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.serve_set_maintenance(req).await
+    }
+
+    async fn reset(&self, req: Request<()>) -> Result<Response<()>, Status> {
+        self.serve_reset(req, Plotter::clear).await
+    }
+
+    async fn get_history(
+        &self,
+        req: Request<functional_units::GetHistoryRequest>,
+    ) -> Result<Response<functional_units::History>, Status> {
+        self.serve_get_history(req).await
     }
 }
 
 #[tonic::async_trait]
 impl functional_units::conveyor_server::Conveyor for ConveyorServerState {
+    type WatchStatusStream = mpsc::Receiver<Result<functional_units::ConveyorStatus, Status>>;
+
+    async fn watch_status(
+        &self,
+        req: Request<()>,
+    ) -> Result<Response<Self::WatchStatusStream>, Status> {
+        self.serve_watch_status(req).await
+    }
+
     async fn status(
         &self,
-        _: Request<()>,
+        req: Request<()>,
     ) -> Result<Response<functional_units::ConveyorStatus>, Status> {
-        let state = self.state.lock().unwrap();
-        let reply = functional_units::ConveyorStatus {
-            name: state.name().to_owned(),
-            has_paper: state.has_paper(),
-            orientation: state.orientation().into(),
-        };
-        println!("status - {:?}", reply);
-        Ok(Response::new(reply))
+        self.serve_status(req).await
     }
 
     async fn turn_to(
         &self,
         req: Request<functional_units::TurnToRequest>,
-    ) -> Result<Response<()>, Status> {
-        let target = functional_units::Orientation::from_i32(req.get_ref().target).unwrap();
-        {
-            let mut state = self.state.lock().unwrap();
-            state.turn_to(target.into());
-        }
-        self.delayer.delay().await;
-        println!("turn_to - {:?}", target);
-
-        Ok(Response::new(()))
+    ) -> Result<Response<functional_units::TurnToResult>, Status> {
+        self.call(
+            "TurnTo",
+            TraceContext::extract(req.metadata()),
+            async move {
+                let value = req.get_ref().target;
+                let target = functional_units::Orientation::from_i32(value).ok_or_else(|| {
+                    status_with_info(
+                        Code::InvalidArgument,
+                        "Unknown orientation",
+                        "UNKNOWN_ORIENTATION",
+                        &[("value", value.to_string())],
+                    )
+                })?;
+                let (res, steps) = {
+                    let mut state = self.lock();
+                    let steps = state.orientation().steps_to(target.into());
+                    if steps > 0 && self.faults.strikes(Fault::Stall) {
+                        (Err(TurnError::Stalled), steps)
+                    } else {
+                        state.turn_to(target.into());
+                        self.publish(&state);
+                        (Ok(()), steps)
+                    }
+                };
+                // Every 90° step takes its own delay, also when the conveyor stalls
+                self.delay(Action::Turn, steps).await;
+                info!(target = ?target, steps = steps, result = ?res, "Turned");
+                let description = format!("Turn to {:?} in {} steps", target, steps);
+                let reply = res.into();
+                self.record("TurnTo", Caller::of(&req), description, &reply);
+                Ok(Response::new(reply))
+            },
+        )
+        .await
     }
 
     async fn push(
         &self,
-        _: Request<()>,
+        req: Request<()>,
     ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
-        let res = {
-            let mut state = self.state.lock().unwrap();
-            state.push()
-        };
-        self.delayer.delay().await;
-        println!("push - {:?}", res);
-        Ok(Response::new(res.into()))
+        self.serve_push(req, Conveyor::push).await
     }
 
     async fn pull(
         &self,
-        _: Request<()>,
+        req: Request<functional_units::PullRequest>,
     ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
-        let res = {
-            let mut state = self.state.lock().unwrap();
-            state.pull()
-        };
-        self.delayer.delay().await;
-        println!("pull - {:?}", res);
-        Ok(Response::new(res.into()))
+        self.serve_pull(req, Conveyor::pull).await
     }
-}
 
-pub struct InputStackServerState {
-    state: Mutex<InputStack>,
-    delayer: Delayer,
-}
+    async fn insert_paper(
+        &self,
+        req: Request<functional_units::PullRequest>,
+    ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
+        self.serve_insert_paper(req, Conveyor::pull).await
+    }
 
-impl InputStackServerState {
-    pub fn new(s: InputStack, delayer: Delayer) -> InputStackServerState {
-        InputStackServerState {
-            state: Mutex::new(s),
-            delayer,
-        }
+    async fn clear_paper(
+        &self,
+        req: Request<()>,
+    ) -> Result<Response<functional_units::RemovedSheets>, Status> {
+        self.serve_clear("ClearPaper", req, Conveyor::clear).await
+    }
+
+    async fn set_maintenance(
+        &self,
+        req: Request<functional_units::SetMaintenanceRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.serve_set_maintenance(req).await
+    }
+
+    async fn reset(&self, req: Request<()>) -> Result<Response<()>, Status> {
+        self.serve_reset(req, Conveyor::reset).await
+    }
+
+    async fn get_history(
+        &self,
+        req: Request<functional_units::GetHistoryRequest>,
+    ) -> Result<Response<functional_units::History>, Status> {
+        self.serve_get_history(req).await
     }
 }
 
 #[tonic::async_trait]
 impl functional_units::input_stack_server::InputStack for InputStackServerState {
+    type WatchStatusStream = mpsc::Receiver<Result<functional_units::InputStackStatus, Status>>;
+
+    async fn watch_status(
+        &self,
+        req: Request<()>,
+    ) -> Result<Response<Self::WatchStatusStream>, Status> {
+        self.serve_watch_status(req).await
+    }
+
     async fn status(
         &self,
-        _: Request<()>,
+        req: Request<()>,
     ) -> Result<Response<functional_units::InputStackStatus>, Status> {
-        let state = self.state.lock().unwrap();
-        let reply = functional_units::InputStackStatus {
-            name: state.name().to_owned(),
-            paper_count: state.paper_count(),
-        };
-        println!("status - {:?}", reply);
-        Ok(Response::new(reply))
+        self.serve_status(req).await
     }
 
     async fn push(
         &self,
-        _: Request<()>,
+        req: Request<()>,
     ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
-        let res = {
-            let mut state = self.state.lock().unwrap();
-            state.push()
-        };
-        self.delayer.delay().await;
-        println!("push - {:?}", res);
-        Ok(Response::new(res.into()))
+        self.serve_push(req, InputStack::push).await
     }
-}
 
-pub struct OutputStackServerState {
-    state: Mutex<OutputStack>,
-    delayer: Delayer,
-}
+    async fn pull(
+        &self,
+        req: Request<functional_units::PullRequest>,
+    ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
+        self.serve_pull(req, |stack, sheet| {
+            stack.pull(sheet);
+            Ok(())
+        })
+        .await
+    }
 
-impl OutputStackServerState {
-    pub fn new(s: OutputStack, delayer: Delayer) -> OutputStackServerState {
-        OutputStackServerState {
-            state: Mutex::new(s),
-            delayer,
-        }
+    async fn insert_paper(
+        &self,
+        req: Request<functional_units::PullRequest>,
+    ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
+        self.serve_insert_paper(req, |stack, sheet| {
+            stack.pull(sheet);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_paper_count(
+        &self,
+        req: Request<functional_units::SetPaperCountRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.call(
+            "SetPaperCount",
+            TraceContext::extract(req.metadata()),
+            async move {
+                let count = req.get_ref().paper_count;
+                {
+                    let mut state = self.lock();
+                    state.set_paper_count(count);
+                    self.publish(&state);
+                }
+                info!(paper_count = count, "Set paper count");
+                let description = format!("Set paper count to {}", count);
+                self.record("SetPaperCount", Caller::of(&req), description, &());
+                Ok(Response::new(()))
+            },
+        )
+        .await
+    }
+
+    async fn refill(&self, req: Request<()>) -> Result<Response<()>, Status> {
+        self.call(
+            "Refill",
+            TraceContext::extract(req.metadata()),
+            async move {
+                {
+                    let mut state = self.lock();
+                    state.refill();
+                    self.publish(&state);
+                }
+                info!("Refilled");
+                self.record("Refill", Caller::of(&req), "Refill".to_owned(), &());
+                Ok(Response::new(()))
+            },
+        )
+        .await
+    }
+
+    async fn set_maintenance(
+        &self,
+        req: Request<functional_units::SetMaintenanceRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.serve_set_maintenance(req).await
+    }
+
+    async fn reset(&self, req: Request<()>) -> Result<Response<()>, Status> {
+        // Refilling drops the sheets taken back
+        self.serve_reset(req, |stack| {
+            let returned = stack.returned().to_vec();
+            stack.refill();
+            returned
+        })
+        .await
+    }
+
+    async fn get_history(
+        &self,
+        req: Request<functional_units::GetHistoryRequest>,
+    ) -> Result<Response<functional_units::History>, Status> {
+        self.serve_get_history(req).await
     }
 }
 
 #[tonic::async_trait]
 impl functional_units::output_stack_server::OutputStack for OutputStackServerState {
+    type WatchStatusStream = mpsc::Receiver<Result<functional_units::OutputStackStatus, Status>>;
+
+    async fn watch_status(
+        &self,
+        req: Request<()>,
+    ) -> Result<Response<Self::WatchStatusStream>, Status> {
+        self.serve_watch_status(req).await
+    }
+
     async fn status(
         &self,
-        _: Request<()>,
+        req: Request<()>,
     ) -> Result<Response<functional_units::OutputStackStatus>, Status> {
-        let state = self.state.lock().unwrap();
-        let reply = functional_units::OutputStackStatus {
-            name: state.name().to_owned(),
-            paper_count: state.paper_count(),
-        };
-        println!("status - {:?}", reply);
-        Ok(Response::new(reply))
+        self.serve_status(req).await
     }
 
     async fn pull(
         &self,
-        _: Request<()>,
+        req: Request<functional_units::PullRequest>,
     ) -> Result<Response<functional_units::PushOrPullResult>, Status> {
-        {
-            let mut state = self.state.lock().unwrap();
-            state.pull();
+        self.serve_pull(req, |stack, sheet| {
+            stack.pull(sheet);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn clear(
+        &self,
+        req: Request<()>,
+    ) -> Result<Response<functional_units::RemovedSheets>, Status> {
+        self.serve_clear("Clear", req, OutputStack::clear).await
+    }
+
+    async fn set_maintenance(
+        &self,
+        req: Request<functional_units::SetMaintenanceRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.serve_set_maintenance(req).await
+    }
+
+    async fn reset(&self, req: Request<()>) -> Result<Response<()>, Status> {
+        self.serve_reset(req, OutputStack::clear).await
+    }
+
+    async fn get_history(
+        &self,
+        req: Request<functional_units::GetHistoryRequest>,
+    ) -> Result<Response<functional_units::History>, Status> {
+        self.serve_get_history(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Delays calls by at most a millisecond
+    fn instant_delayer() -> Delayer {
+        Delayer::new(Duration::from_millis(0), Duration::from_millis(1))
+    }
+
+    #[tokio::test]
+    async fn maintenance_and_reset() {
+        use functional_units::conveyor_server::Conveyor as _;
+
+        let state = ConveyorServerState::new(Conveyor::new("conv1"), instant_delayer());
+        let req = functional_units::SetMaintenanceRequest { enabled: true };
+        state.set_maintenance(Request::new(req)).await.unwrap();
+        assert_eq!(Condition::Maintenance, state.health().condition());
+
+        let sheet = functional_units::PullRequest {
+            sheet: Some((&Sheet::new("Main-1")).into()),
+        };
+        state.insert_paper(Request::new(sheet)).await.unwrap();
+        lock(&state.state, &state.health).turn_to(Orientation::North);
+
+        state.reset(Request::new(())).await.unwrap();
+        assert_eq!(Condition::Operational, state.health().condition());
+        let conv = lock(&state.state, &state.health);
+        assert!(!conv.has_paper());
+        assert_eq!(&Orientation::East, conv.orientation());
+    }
+
+    #[tokio::test]
+    async fn call_metrics() {
+        use functional_units::input_stack_server::InputStack as _;
+
+        let state = InputStackServerState::new(InputStack::new("Main", 1), instant_delayer());
+        state.push(Request::new(())).await.unwrap();
+        state.push(Request::new(())).await.unwrap();
+        let metrics = state.metrics().render();
+        assert!(metrics.contains("fiab_calls_total{code=\"Ok\",method=\"Push\"} 1\n"));
+        assert!(metrics.contains("fiab_calls_total{code=\"Empty\",method=\"Push\"} 1\n"));
+        assert!(metrics.contains("fiab_delay_seconds_count{action=\"Push\"} 2\n"));
+        assert!(metrics.contains("fiab_paper_count 0\n"));
+    }
+
+    #[tokio::test]
+    async fn insert_paper_without_faults() {
+        use functional_units::input_stack_server::InputStack as _;
+
+        let state = InputStackServerState::new(InputStack::new("Main", 0), instant_delayer())
+            .with_faults("jam = { probability = 1.0 }".parse().unwrap());
+        let req = || functional_units::PullRequest {
+            sheet: Some((&Sheet::new("Main-1")).into()),
+        };
+        let reply = state.pull(Request::new(req())).await.unwrap().into_inner();
+        assert_eq!(
+            functional_units::push_or_pull_result::Code::Jammed as i32,
+            reply.code
+        );
+        let reply = state.insert_paper(Request::new(req())).await;
+        assert_eq!(0, reply.unwrap().into_inner().code);
+        assert_eq!(1, lock(&state.state, &state.health).paper_count());
+        let metrics = state.metrics().render();
+        assert!(metrics.contains("fiab_delay_seconds_count{action=\"Pull\"} 1\n"));
+    }
+
+    #[tokio::test]
+    async fn pen_failure_after_checks() {
+        use functional_units::plotter_server::Plotter as _;
+
+        let state = PlotterServerState::new(
+            Plotter::new("plotter1", &[PlotterFunction::DrawRed]),
+            instant_delayer(),
+        )
+        .with_faults("pen_failure = { at_calls = [1] }".parse().unwrap());
+        let plot = |function: functional_units::PlotterFunction| {
+            Request::new(functional_units::PlotRequest {
+                function: function.into(),
+            })
+        };
+        let code = |reply: Result<Response<functional_units::PlotResult>, Status>| {
+            functional_units::plot_result::Code::from_i32(reply.unwrap().into_inner().code)
+        };
+
+        let reply = state
+            .plot(plot(functional_units::PlotterFunction::DrawRed))
+            .await;
+        assert_eq!(
+            Some(functional_units::plot_result::Code::NoPaper),
+            code(reply)
+        );
+        lock(&state.state, &state.health)
+            .pull(Sheet::new("Main-1"))
+            .unwrap();
+        let reply = state
+            .plot(plot(functional_units::PlotterFunction::DrawBlue))
+            .await;
+        assert_eq!(
+            Some(functional_units::plot_result::Code::UnsupportedFunction),
+            code(reply)
+        );
+        // The first plot that would work is the first the fault counts
+        let reply = state
+            .plot(plot(functional_units::PlotterFunction::DrawRed))
+            .await;
+        assert_eq!(
+            Some(functional_units::plot_result::Code::PenFailure),
+            code(reply)
+        );
+        let plotter = lock(&state.state, &state.health);
+        assert_eq!(Some(&Sheet::new("Main-1")), plotter.sheet());
+    }
+
+    #[tokio::test]
+    async fn history_of_calls() {
+        use functional_units::conveyor_server::Conveyor as _;
+
+        let state = ConveyorServerState::new(Conveyor::new("conv1"), instant_delayer());
+        let mut turn = Request::new(functional_units::TurnToRequest {
+            target: functional_units::Orientation::West.into(),
+        });
+        turn.metadata_mut()
+            .insert("caller", "orchestrator".parse().unwrap());
+        state.turn_to(turn).await.unwrap();
+        state.push(Request::new(())).await.unwrap();
+        // Only changes are recorded
+        state.status(Request::new(())).await.unwrap();
+
+        let req = |limit| {
+            Request::new(functional_units::GetHistoryRequest {
+                since: None,
+                until: None,
+                limit,
+            })
+        };
+        let events = state.get_history(req(0)).await.unwrap().into_inner().events;
+        let described: Vec<_> = events
+            .iter()
+            .map(|e| (e.method.as_str(), e.description.as_str(), e.result.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("TurnTo", "Turn to West in 2 steps", "Ok"),
+                ("Push", "Push", "Empty")
+            ],
+            described
+        );
+        assert_eq!("orchestrator", events[0].caller);
+
+        let latest = state.get_history(req(1)).await.unwrap().into_inner().events;
+        assert_eq!(vec![events[1].clone()], latest);
+    }
+
+    #[tokio::test]
+    async fn traced_calls() {
+        use functional_units::conveyor_server::Conveyor as _;
+
+        let path = std::env::temp_dir().join(format!("spans-{}.json", std::process::id()));
+        let state = ConveyorServerState::new(Conveyor::new("conv1"), instant_delayer())
+            .with_exporter(Arc::new(SpanExporter::to_file(&path).unwrap()));
+        let parent = TraceContext::new_root();
+        let mut push = Request::new(());
+        parent.inject(push.metadata_mut());
+        state.push(push).await.unwrap();
+        state.status(Request::new(())).await.unwrap();
+
+        let spans = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let spans: Vec<serde_json::Value> = spans
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, spans.len());
+        // Calls join the caller's trace, or start one without it
+        assert_eq!(parent.trace_id(), spans[0]["traceId"]);
+        assert_eq!(parent.span_id(), spans[0]["parentSpanId"]);
+        assert_eq!("functional_units.Conveyor/Push", spans[0]["name"]);
+        assert!(spans[0]["attributes"].as_array().unwrap().contains(
+            &serde_json::json!({ "key": "result", "value": { "stringValue": "Empty" } })
+        ));
+        assert_ne!(parent.trace_id(), spans[1]["traceId"]);
+        assert!(spans[1].get("parentSpanId").is_none());
+    }
+
+    #[tokio::test]
+    async fn wear_until_maintenance() {
+        use functional_units::conveyor_server::Conveyor as _;
+
+        let state = ConveyorServerState::new(Conveyor::new("conv1"), instant_delayer())
+            .with_wear("turns = 2".parse().unwrap());
+        let status = || async { state.status(Request::new(())).await.unwrap().into_inner() };
+        for &target in &[
+            functional_units::Orientation::West,
+            functional_units::Orientation::North,
+        ] {
+            let req = functional_units::TurnToRequest {
+                target: target.into(),
+            };
+            state.turn_to(Request::new(req)).await.unwrap();
         }
-        self.delayer.delay().await;
-        println!("pull");
-        Ok(Response::new(Ok(()).into()))
+        let worn = status().await;
+        assert_eq!(2, worn.usage.as_ref().unwrap().turns);
+        assert_eq!(270, worn.usage.as_ref().unwrap().degrees_rotated);
+        assert!(worn.maintenance_due);
+
+        // Only leaving maintenance services the unit
+        state.reset(Request::new(())).await.unwrap();
+        assert!(status().await.maintenance_due);
+        let maintenance =
+            |enabled| Request::new(functional_units::SetMaintenanceRequest { enabled });
+        state.set_maintenance(maintenance(true)).await.unwrap();
+        state.set_maintenance(maintenance(false)).await.unwrap();
+        let serviced = status().await;
+        assert_eq!(0.0, serviced.wear);
+        assert!(!serviced.maintenance_due);
+        assert_eq!(worn.usage.unwrap().turns, serviced.usage.unwrap().turns);
+    }
+
+    #[tokio::test]
+    async fn seeded_delays() {
+        let delayer = || {
+            Delayer::new(Duration::from_secs(60), Duration::from_secs(120))
+                .seeded(42)
+                .with_clock(Clock::Virtual)
+        };
+        let (first, second) = (delayer(), delayer());
+        for &action in &[Action::Plot, Action::Push, Action::Turn] {
+            first.delay(action).await;
+            second.delay(action).await;
+            assert_eq!(first.elapsed(), second.elapsed());
+        }
+        assert!(first.elapsed() >= Duration::from_secs(180));
+    }
+
+    #[tokio::test]
+    async fn virtual_timestamps() {
+        use functional_units::conveyor_server::Conveyor as _;
+
+        let hour = Duration::from_secs(3600);
+        let before = SystemTime::now();
+        let state = ConveyorServerState::new(
+            Conveyor::new("conv1"),
+            Delayer::new(hour, hour).with_clock(Clock::Virtual),
+        );
+        let req = functional_units::TurnToRequest {
+            target: functional_units::Orientation::West.into(),
+        };
+        state.turn_to(Request::new(req)).await.unwrap();
+
+        // Stamped after the two steps on the unit's clock, not the system's
+        let history = state.get_history(Request::new(Default::default())).await;
+        let time = history.unwrap().into_inner().events[0]
+            .time
+            .clone()
+            .unwrap();
+        assert!(SystemTime::try_from(time).unwrap() >= before + 2 * hour);
+        let status = state.status(Request::new(())).await.unwrap().into_inner();
+        assert_eq!(7200, status.usage.unwrap().uptime_seconds);
+    }
+
+    #[tokio::test]
+    async fn watch_sends_changes_only() {
+        let mut conv = Conveyor::new("conv1");
+        let state = ConveyorServerState::new(Conveyor::new("conv1"), instant_delayer());
+        let mut statuses = watch_status(state.watch.clone());
+        let watched = |conv: &Conveyor| Ok(Conveyor::watched(state.status_of(conv)));
+        assert_eq!(
+            Some(watched(&conv)),
+            statuses.recv().await.map(|r| r.map_err(|e| e.code()))
+        );
+
+        // Failed calls do not change the status
+        state.publish(&conv);
+        conv.turn_to(Orientation::North);
+        state.publish(&conv);
+        assert_eq!(
+            Some(watched(&conv)),
+            statuses.recv().await.map(|r| r.map_err(|e| e.code()))
+        );
     }
 }
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use tokio::sync::mpsc;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+use super::reflection_proto::server_reflection_request::MessageRequest;
+use super::reflection_proto::server_reflection_response::MessageResponse;
+use super::reflection_proto::server_reflection_server;
+use super::reflection_proto::{
+    ErrorResponse, FileDescriptorResponse, ListServiceResponse, ServerReflectionRequest,
+    ServerReflectionResponse, ServiceResponse,
+};
+
+pub use super::reflection_proto::server_reflection_server::ServerReflectionServer;
+
+/// Descriptors of all protos compiled by `build.rs`, including their imports
+const DESCRIPTORS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/descriptors.bin"));
+
+/// Index over the compiled descriptors to answer reflection requests
+struct Descriptors {
+    files: HashMap<String, FileDescriptorProto>,
+    /// File declaring each fully-qualified message, enum, service and method
+    symbols: HashMap<String, String>,
+    services: Vec<String>,
+}
+
+impl Descriptors {
+    fn new(services: &[&str]) -> Descriptors {
+        let set = FileDescriptorSet::decode(DESCRIPTORS).expect("build.rs wrote valid descriptors");
+        let mut descriptors = Descriptors {
+            files: HashMap::new(),
+            symbols: HashMap::new(),
+            services: services.iter().map(|&s| s.to_owned()).collect(),
+        };
+        for file in set.file {
+            descriptors.add(file);
+        }
+        descriptors
+    }
+
+    fn add(&mut self, file: FileDescriptorProto) {
+        let name = file.name().to_owned();
+        let prefix = match file.package() {
+            "" => String::new(),
+            package => format!("{}.", package),
+        };
+        for message in &file.message_type {
+            self.add_message(&name, &prefix, message);
+        }
+        for e in &file.enum_type {
+            self.symbols
+                .insert(format!("{}{}", prefix, e.name()), name.clone());
+        }
+        for service in &file.service {
+            let service_name = format!("{}{}", prefix, service.name());
+            for method in &service.method {
+                self.symbols
+                    .insert(format!("{}.{}", service_name, method.name()), name.clone());
+            }
+            self.symbols.insert(service_name, name.clone());
+        }
+        self.files.insert(name, file);
+    }
+
+    fn add_message(&mut self, file: &str, prefix: &str, message: &DescriptorProto) {
+        let full_name = format!("{}{}", prefix, message.name());
+        let nested = format!("{}.", full_name);
+        for inner in &message.nested_type {
+            self.add_message(file, &nested, inner);
+        }
+        for e in &message.enum_type {
+            self.symbols
+                .insert(format!("{}{}", nested, e.name()), file.to_owned());
+        }
+        self.symbols.insert(full_name, file.to_owned());
+    }
+
+    /// Serialized descriptors of `name` followed by all files it depends on
+    fn file_with_dependencies(&self, name: &str) -> Option<Vec<Vec<u8>>> {
+        self.files.get(name)?;
+        let mut names = vec![name];
+        let mut i = 0;
+        while i < names.len() {
+            if let Some(file) = self.files.get(names[i]) {
+                for dependency in &file.dependency {
+                    if !names.contains(&dependency.as_str()) {
+                        names.push(dependency);
+                    }
+                }
+            }
+            i += 1;
+        }
+        let encoded = names
+            .into_iter()
+            .filter_map(|name| self.files.get(name))
+            .map(|file| {
+                let mut buf = Vec::new();
+                file.encode(&mut buf).expect("Vec grows as needed");
+                buf
+            })
+            .collect();
+        Some(encoded)
+    }
+
+    fn respond(&self, request: &MessageRequest) -> MessageResponse {
+        let files = match request {
+            MessageRequest::FileByFilename(name) => self
+                .file_with_dependencies(name)
+                .ok_or_else(|| format!("Unknown file '{}'", name)),
+            MessageRequest::FileContainingSymbol(symbol) => self
+                .symbols
+                .get(symbol)
+                .and_then(|file| self.file_with_dependencies(file))
+                .ok_or_else(|| format!("Unknown symbol '{}'", symbol)),
+            MessageRequest::ListServices(_) => {
+                return MessageResponse::ListServicesResponse(ListServiceResponse {
+                    service: self
+                        .services
+                        .iter()
+                        .map(|name| ServiceResponse { name: name.clone() })
+                        .collect(),
+                });
+            }
+            // None of the protos declare extensions
+            MessageRequest::FileContainingExtension(_)
+            | MessageRequest::AllExtensionNumbersOfType(_) => {
+                Err("Extensions are not supported".to_owned())
+            }
+        };
+        match files {
+            Ok(file_descriptor_proto) => {
+                MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+                    file_descriptor_proto,
+                })
+            }
+            Err(error_message) => MessageResponse::ErrorResponse(ErrorResponse {
+                error_code: Code::NotFound as i32,
+                error_message,
+            }),
+        }
+    }
+}
+
+/// Implements the gRPC server reflection protocol, so tools like grpcurl can
+/// explore a unit without being handed its protos
+pub struct ReflectionServerState {
+    descriptors: Arc<Descriptors>,
+}
+
+impl ReflectionServerState {
+    /// `services` are the full names of the services served next to it
+    pub fn new(services: &[&str]) -> ReflectionServerState {
+        ReflectionServerState {
+            descriptors: Arc::new(Descriptors::new(services)),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl server_reflection_server::ServerReflection for ReflectionServerState {
+    type ServerReflectionInfoStream = mpsc::Receiver<Result<ServerReflectionResponse, Status>>;
+
+    async fn server_reflection_info(
+        &self,
+        request: Request<Streaming<ServerReflectionRequest>>,
+    ) -> Result<Response<Self::ServerReflectionInfoStream>, Status> {
+        let mut requests = request.into_inner();
+        let descriptors = self.descriptors.clone();
+        let (mut tx, rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            while let Ok(Some(request)) = requests.message().await {
+                let message_response = request
+                    .message_request
+                    .as_ref()
+                    .map(|r| descriptors.respond(r));
+                let response = ServerReflectionResponse {
+                    valid_host: request.host.clone(),
+                    original_request: Some(request),
+                    message_response,
+                };
+                if tx.send(Ok(response)).await.is_err() {
+                    // The client hung up
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptors() -> Descriptors {
+        Descriptors::new(&["functional_units.Plotter", "grpc.health.v1.Health"])
+    }
+
+    fn files(response: MessageResponse) -> Vec<String> {
+        match response {
+            MessageResponse::FileDescriptorResponse(r) => r
+                .file_descriptor_proto
+                .iter()
+                .map(|buf| {
+                    FileDescriptorProto::decode(&buf[..])
+                        .unwrap()
+                        .name()
+                        .to_owned()
+                })
+                .collect(),
+            other => panic!("Unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn list_services() {
+        match descriptors().respond(&MessageRequest::ListServices(String::new())) {
+            MessageResponse::ListServicesResponse(r) => {
+                let names: Vec<_> = r.service.into_iter().map(|s| s.name).collect();
+                assert_eq!(
+                    vec!["functional_units.Plotter", "grpc.health.v1.Health"],
+                    names
+                );
+            }
+            other => panic!("Unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn file_containing_symbol() {
+        let descriptors = descriptors();
+        for symbol in &[
+            "functional_units.Plotter",
+            "functional_units.Plotter.Plot",
+            "functional_units.PlotResult.Code",
+            "functional_units.Orientation",
+        ] {
+            let request = MessageRequest::FileContainingSymbol((*symbol).to_owned());
+            assert_eq!(
+                vec![
+                    "functional_units.proto",
+                    "google/protobuf/empty.proto",
+                    "google/protobuf/timestamp.proto"
+                ],
+                files(descriptors.respond(&request))
+            );
+        }
+    }
+
+    #[test]
+    fn file_by_filename() {
+        let request = MessageRequest::FileByFilename("health.proto".to_owned());
+        assert_eq!(vec!["health.proto"], files(descriptors().respond(&request)));
+    }
+
+    #[test]
+    fn unknown_symbol() {
+        let request = MessageRequest::FileContainingSymbol("functional_units.Printer".to_owned());
+        match descriptors().respond(&request) {
+            MessageResponse::ErrorResponse(e) => assert_eq!(Code::NotFound as i32, e.error_code),
+            other => panic!("Unexpected response {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::PlotterFunction;
+
+/// A paper sheet moving over the shop floor
+#[derive(PartialEq, Debug, Clone)]
+pub struct Sheet {
+    id: String,
+    functions: Vec<PlotterFunction>,
+}
+
+impl Sheet {
+    pub fn new(id: &str) -> Sheet {
+        Sheet {
+            id: String::from(id),
+            functions: Vec::new(),
+        }
+    }
+
+    pub fn with_functions(id: &str, functions: Vec<PlotterFunction>) -> Sheet {
+        Sheet {
+            id: String::from(id),
+            functions,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Functions applied to the sheet, in the order they were plotted
+    pub fn functions(&self) -> &[PlotterFunction] {
+        &self.functions
+    }
+
+    pub fn stamp(&mut self, function: PlotterFunction) {
+        self.functions.push(function);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new() {
+        let sheet = Sheet::new("Main-1");
+        assert_eq!("Main-1", sheet.id());
+        assert!(sheet.functions().is_empty());
+    }
+
+    #[test]
+    fn stamp() {
+        let mut sheet = Sheet::new("Main-1");
+        sheet.stamp(PlotterFunction::DrawRed);
+        sheet.stamp(PlotterFunction::DrawBlue);
+        sheet.stamp(PlotterFunction::DrawRed);
+        assert_eq!(
+            &[
+                PlotterFunction::DrawRed,
+                PlotterFunction::DrawBlue,
+                PlotterFunction::DrawRed
+            ],
+            sheet.functions()
+        );
+    }
+}
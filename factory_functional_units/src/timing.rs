@@ -0,0 +1,301 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Deserialize;
+
+/// Operation of a unit that takes time on the real line
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Action {
+    Plot,
+    /// A single 90° step of a conveyor
+    Turn,
+    Push,
+    Pull,
+}
+
+/// Distribution the duration of an action is drawn from, in milliseconds
+#[derive(PartialEq, Debug, Clone, Deserialize)]
+#[serde(tag = "distribution")]
+pub enum Distribution {
+    Fixed {
+        ms: f64,
+    },
+    Uniform {
+        min_ms: f64,
+        max_ms: f64,
+    },
+    /// Draws below zero are cut off at zero
+    Normal {
+        mean_ms: f64,
+        std_dev_ms: f64,
+    },
+    Exponential {
+        mean_ms: f64,
+    },
+}
+
+impl Distribution {
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        let ms = match *self {
+            Distribution::Fixed { ms } => ms,
+            Distribution::Uniform { min_ms, max_ms } if min_ms < max_ms => {
+                rng.gen_range(min_ms, max_ms)
+            }
+            Distribution::Uniform { min_ms, .. } => min_ms,
+            Distribution::Normal {
+                mean_ms,
+                std_dev_ms,
+            } => {
+                // Box-Muller transform, 1 - u keeps the logarithm finite
+                let u: f64 = rng.gen();
+                let v: f64 = rng.gen();
+                let z = (-2.0 * (1.0 - u).ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+                mean_ms + std_dev_ms * z
+            }
+            Distribution::Exponential { mean_ms } => {
+                let u: f64 = rng.gen();
+                -mean_ms * (1.0 - u).ln()
+            }
+        };
+        Duration::from_micros((ms.max(0.0) * 1000.0) as u64)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let values = match *self {
+            Distribution::Fixed { ms } => vec![("ms", ms)],
+            Distribution::Uniform { min_ms, max_ms } => {
+                if min_ms > max_ms {
+                    return Err(format!("min_ms {} is above max_ms {}", min_ms, max_ms));
+                }
+                vec![("min_ms", min_ms), ("max_ms", max_ms)]
+            }
+            Distribution::Normal {
+                mean_ms,
+                std_dev_ms,
+            } => vec![("mean_ms", mean_ms), ("std_dev_ms", std_dev_ms)],
+            Distribution::Exponential { mean_ms } => vec![("mean_ms", mean_ms)],
+        };
+        for (name, value) in values {
+            if !(value >= 0.0 && value.is_finite()) {
+                return Err(format!("{} must be a positive number, got {}", name, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Distribution {
+    fn default() -> Self {
+        Distribution::Uniform {
+            min_ms: 100.0,
+            max_ms: 500.0,
+        }
+    }
+}
+
+/// How delays pass for a unit.
+///
+/// Under a scaled or virtual clock the unit's history, spans and uptime
+/// follow the sum of its delays. Logs keep wall-clock time, as all units of
+/// a process share the logger.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Clock {
+    /// Sleeps as long as the operations take
+    Real,
+    /// Sleeps the time divided by the factor, 3600 runs an hour in a second
+    Scaled(f64),
+    /// Never sleeps and only advances the unit's virtual time
+    Virtual,
+}
+
+impl Clock {
+    /// Real time spent sleeping for `virtual_time`
+    pub fn real_time(&self, virtual_time: Duration) -> Duration {
+        match *self {
+            Clock::Real => virtual_time,
+            Clock::Scaled(factor) => Duration::from_secs_f64(virtual_time.as_secs_f64() / factor),
+            Clock::Virtual => Duration::from_secs(0),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TimingError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    /// A distribution has parameters no durations can be drawn with
+    Invalid {
+        action: Action,
+        reason: String,
+    },
+}
+
+impl Display for TimingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimingError::Io(e) => write!(f, "Could not read timing: {}", e),
+            TimingError::Parse(e) => write!(f, "Could not parse timing: {}", e),
+            TimingError::Invalid { action, reason } => {
+                write!(f, "Invalid timing of {:?}: {}", action, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TimingError {}
+
+impl From<std::io::Error> for TimingError {
+    fn from(e: std::io::Error) -> Self {
+        TimingError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for TimingError {
+    fn from(e: toml::de::Error) -> Self {
+        TimingError::Parse(e)
+    }
+}
+
+/// Durations of the operations of a unit.
+///
+/// Operations missing from a timing file take 100–500 ms.
+#[derive(PartialEq, Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Timing {
+    plot: Distribution,
+    turn: Distribution,
+    push: Distribution,
+    pull: Distribution,
+}
+
+impl Timing {
+    /// Same distribution for every operation
+    pub fn uniform(distribution: Distribution) -> Timing {
+        Timing {
+            plot: distribution.clone(),
+            turn: distribution.clone(),
+            push: distribution.clone(),
+            pull: distribution,
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Timing, TimingError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    pub fn of(&self, action: Action) -> &Distribution {
+        match action {
+            Action::Plot => &self.plot,
+            Action::Turn => &self.turn,
+            Action::Push => &self.push,
+            Action::Pull => &self.pull,
+        }
+    }
+}
+
+impl FromStr for Timing {
+    type Err = TimingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let timing: Timing = toml::from_str(s)?;
+        for &action in &[Action::Plot, Action::Turn, Action::Push, Action::Pull] {
+            timing
+                .of(action)
+                .validate()
+                .map_err(|reason| TimingError::Invalid { action, reason })?;
+        }
+        Ok(timing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMING: &str = r#"
+        [plot]
+        distribution = "Normal"
+        mean_ms = 300.0
+        std_dev_ms = 50.0
+
+        [turn]
+        distribution = "Fixed"
+        ms = 150.0
+
+        [pull]
+        distribution = "Exponential"
+        mean_ms = 80.0
+    "#;
+
+    #[test]
+    fn parse() {
+        let timing: Timing = TIMING.parse().unwrap();
+        assert_eq!(
+            &Distribution::Normal {
+                mean_ms: 300.0,
+                std_dev_ms: 50.0
+            },
+            timing.of(Action::Plot)
+        );
+        assert_eq!(&Distribution::Fixed { ms: 150.0 }, timing.of(Action::Turn));
+        assert_eq!(&Distribution::default(), timing.of(Action::Push));
+        assert_eq!(
+            &Distribution::Exponential { mean_ms: 80.0 },
+            timing.of(Action::Pull)
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let timing = TIMING.replace("ms = 150.0", "ms = -1.0");
+        match timing.parse::<Timing>() {
+            Err(TimingError::Invalid { action, .. }) => assert_eq!(Action::Turn, action),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match "[push]\ndistribution = \"Poisson\"".parse::<Timing>() {
+            Err(TimingError::Parse(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sample() {
+        let mut rng = rand::thread_rng();
+        let fixed = Distribution::Fixed { ms: 150.0 };
+        assert_eq!(Duration::from_millis(150), fixed.sample(&mut rng));
+
+        let uniform = Distribution::Uniform {
+            min_ms: 10.0,
+            max_ms: 20.0,
+        };
+        let normal = Distribution::Normal {
+            mean_ms: 10.0,
+            std_dev_ms: 20.0,
+        };
+        for _ in 0..100 {
+            let d = uniform.sample(&mut rng);
+            assert!(d >= Duration::from_millis(10) && d < Duration::from_millis(20));
+            // Cut off at zero instead of panicking
+            normal.sample(&mut rng);
+        }
+    }
+
+    #[test]
+    fn clock() {
+        let hour = Duration::from_secs(3600);
+        assert_eq!(hour, Clock::Real.real_time(hour));
+        assert_eq!(
+            Duration::from_secs(1),
+            Clock::Scaled(3600.0).real_time(hour)
+        );
+        assert_eq!(Duration::from_secs(0), Clock::Virtual.real_time(hour));
+    }
+
+    #[test]
+    fn example_timing() {
+        Timing::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/timing.toml")).unwrap();
+    }
+}
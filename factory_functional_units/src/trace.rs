@@ -0,0 +1,334 @@
+use std::fmt::{Display, Formatter, Write as _};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+use tonic::metadata::MetadataMap;
+
+/// Metadata key of the W3C trace context
+const TRACEPARENT: &str = "traceparent";
+
+/// Position of a call in a trace, as propagated in the W3C `traceparent`
+/// header, e.g. `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct TraceContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    sampled: bool,
+}
+
+impl TraceContext {
+    /// First span of a new trace
+    pub fn new_root() -> TraceContext {
+        TraceContext {
+            trace_id: random_id(),
+            span_id: random_id(),
+            sampled: true,
+        }
+    }
+
+    /// New span within the same trace
+    pub fn child(&self) -> TraceContext {
+        TraceContext {
+            span_id: random_id(),
+            ..*self
+        }
+    }
+
+    pub fn trace_id(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    pub fn span_id(&self) -> String {
+        hex(&self.span_id)
+    }
+
+    /// Parses a `traceparent` value, `None` if it is invalid
+    pub fn parse(s: &str) -> Option<TraceContext> {
+        let parts: Vec<_> = s.trim().split('-').collect();
+        if parts.len() < 4 || parts[0].len() != 2 || parts[0] == "ff" {
+            return None;
+        }
+        // Later versions may append fields, version 00 has exactly four
+        if parts[0] == "00" && parts.len() != 4 {
+            return None;
+        }
+        let version = unhex(parts[0])?;
+        let flags = unhex(parts[3])?;
+        if version.len() != 1 || flags.len() != 1 {
+            return None;
+        }
+        let mut context = TraceContext {
+            trace_id: [0; 16],
+            span_id: [0; 8],
+            sampled: flags[0] & 1 == 1,
+        };
+        let trace_id = unhex(parts[1])?;
+        let span_id = unhex(parts[2])?;
+        if trace_id.len() != 16 || span_id.len() != 8 {
+            return None;
+        }
+        context.trace_id.copy_from_slice(&trace_id);
+        context.span_id.copy_from_slice(&span_id);
+        // All zero ids are invalid
+        if context.trace_id == [0; 16] || context.span_id == [0; 8] {
+            return None;
+        }
+        Some(context)
+    }
+
+    /// Context a caller sent along, `None` if it sent none or an invalid one
+    pub fn extract(metadata: &MetadataMap) -> Option<TraceContext> {
+        let value = metadata.get(TRACEPARENT)?.to_str().ok()?;
+        TraceContext::parse(value)
+    }
+
+    /// Sends the context along with a call
+    pub fn inject(&self, metadata: &mut MetadataMap) {
+        let value = self
+            .to_string()
+            .parse()
+            .expect("traceparent is always valid ASCII");
+        metadata.insert(TRACEPARENT, value);
+    }
+}
+
+impl Display for TraceContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            self.trace_id(),
+            self.span_id(),
+            self.sampled as u8
+        )
+    }
+}
+
+fn random_id<T: Default + AsMut<[u8]>>() -> T {
+    let mut id = T::default();
+    // Ids must not be all zero
+    while id.as_mut().iter().all(|&b| b == 0) {
+        rand::Rng::fill(&mut rand::thread_rng(), id.as_mut());
+    }
+    id
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(s, "{:02x}", b);
+    }
+    s
+}
+
+/// Lowercase hex digits only, as the spec demands
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some(digit(*high)? << 4 | digit(*low)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn digit(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        _ => None,
+    }
+}
+
+/// Role of a span in a call
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum SpanKind {
+    /// Handles a call
+    Server,
+    /// Work within a process
+    Internal,
+}
+
+/// Operation timed as part of a trace
+#[derive(Debug, Clone)]
+pub struct TraceSpan {
+    name: String,
+    kind: SpanKind,
+    context: TraceContext,
+    parent_span_id: Option<[u8; 8]>,
+    start: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+}
+
+impl TraceSpan {
+    /// Starts a span as child of `parent`, or of a new trace without one
+    pub fn start(name: &str, kind: SpanKind, parent: Option<TraceContext>) -> TraceSpan {
+        TraceSpan::start_at(name, kind, parent, SystemTime::now())
+    }
+
+    /// Starts a span at `start` on a clock other than the system's
+    pub fn start_at(
+        name: &str,
+        kind: SpanKind,
+        parent: Option<TraceContext>,
+        start: SystemTime,
+    ) -> TraceSpan {
+        TraceSpan {
+            name: name.to_owned(),
+            kind,
+            context: parent.map_or_else(TraceContext::new_root, |p| p.child()),
+            parent_span_id: parent.map(|p| p.span_id),
+            start,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Context to pass on to the calls made within the span
+    pub fn context(&self) -> TraceContext {
+        self.context
+    }
+
+    pub fn set_attribute(&mut self, key: &'static str, value: String) {
+        self.attributes.push((key, value));
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+/// Writes finished spans to a file, one per line in the OTLP JSON encoding,
+/// ready to be picked up by a collector
+pub struct SpanExporter {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl SpanExporter {
+    /// Appends to the file, creating it if needed
+    pub fn to_file<P: AsRef<Path>>(path: P) -> std::io::Result<SpanExporter> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(SpanExporter::new(Box::new(file)))
+    }
+
+    fn new(out: Box<dyn Write + Send>) -> SpanExporter {
+        SpanExporter {
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Ends the span now and writes it
+    pub fn export(&self, span: TraceSpan) {
+        self.export_at(span, SystemTime::now());
+    }
+
+    /// Ends the span at `end` on the clock it was started with and writes it
+    pub fn export_at(&self, span: TraceSpan, end: SystemTime) {
+        let line = encode(&span, end);
+        let mut out = self.out.lock().unwrap_or_else(PoisonError::into_inner);
+        // Losing a span must never fail a call
+        let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+    }
+}
+
+fn encode(span: &TraceSpan, end: SystemTime) -> String {
+    let attributes: Vec<_> = span
+        .attributes
+        .iter()
+        .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+        .collect();
+    let mut line = json!({
+        "traceId": span.context.trace_id(),
+        "spanId": span.context.span_id(),
+        "name": span.name,
+        "kind": match span.kind {
+            SpanKind::Server => "SPAN_KIND_SERVER",
+            SpanKind::Internal => "SPAN_KIND_INTERNAL",
+        },
+        // 64 bit nanoseconds are strings in the JSON encoding of OTLP
+        "startTimeUnixNano": unix_nanos(span.start).to_string(),
+        "endTimeUnixNano": unix_nanos(end).to_string(),
+        "attributes": attributes,
+    });
+    if let Some(parent) = &span.parent_span_id {
+        line["parentSpanId"] = json!(hex(parent));
+    }
+    line.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn parse() {
+        let context = TraceContext::parse(TRACEPARENT).unwrap();
+        assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", context.trace_id());
+        assert_eq!("00f067aa0ba902b7", context.span_id());
+        assert_eq!(TRACEPARENT, context.to_string());
+
+        for invalid in &[
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-00",
+        ] {
+            assert_eq!(None, TraceContext::parse(invalid), "{}", invalid);
+        }
+        // Later versions may add fields
+        let future = format!("cc{}-extra", &TRACEPARENT[2..]);
+        assert!(TraceContext::parse(&future).is_some());
+    }
+
+    #[test]
+    fn metadata() {
+        let context = TraceContext::new_root();
+        let mut metadata = MetadataMap::new();
+        assert_eq!(None, TraceContext::extract(&metadata));
+        context.inject(&mut metadata);
+        assert_eq!(Some(context), TraceContext::extract(&metadata));
+    }
+
+    #[test]
+    fn child_spans() {
+        let parent = TraceContext::parse(TRACEPARENT).unwrap();
+        let mut span = TraceSpan::start(
+            "functional_units.Plotter/Plot",
+            SpanKind::Server,
+            Some(parent),
+        );
+        assert_eq!(parent.trace_id(), span.context().trace_id());
+        assert_ne!(parent.span_id(), span.context().span_id());
+
+        span.start = UNIX_EPOCH + Duration::from_millis(1500);
+        span.set_attribute("unit", "Plotter 1".to_owned());
+        let line: serde_json::Value =
+            serde_json::from_str(&encode(&span, UNIX_EPOCH + Duration::from_secs(2))).unwrap();
+        assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", line["traceId"]);
+        assert_eq!(span.context().span_id(), line["spanId"]);
+        assert_eq!("00f067aa0ba902b7", line["parentSpanId"]);
+        assert_eq!("functional_units.Plotter/Plot", line["name"]);
+        assert_eq!("SPAN_KIND_SERVER", line["kind"]);
+        assert_eq!("1500000000", line["startTimeUnixNano"]);
+        assert_eq!("2000000000", line["endTimeUnixNano"]);
+        assert_eq!(
+            json!([{ "key": "unit", "value": { "stringValue": "Plotter 1" } }]),
+            line["attributes"]
+        );
+
+        let root = TraceSpan::start("order", SpanKind::Internal, None);
+        assert!(!encode(&root, SystemTime::now()).contains("parentSpanId"));
+    }
+}
@@ -0,0 +1,233 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Counters of how much a unit was used over its lifetime
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub struct Usage {
+    pub plots: u64,
+    /// Turns that moved the conveyor, whatever their number of steps
+    pub turns: u64,
+    pub degrees_rotated: u64,
+    /// Sheets pushed on to the next unit
+    pub sheets_moved: u64,
+    pub uptime: Duration,
+}
+
+impl Usage {
+    /// Usage since the unit was used as much as `earlier`
+    pub fn since(&self, earlier: &Usage) -> Usage {
+        Usage {
+            plots: self.plots.saturating_sub(earlier.plots),
+            turns: self.turns.saturating_sub(earlier.turns),
+            degrees_rotated: self.degrees_rotated.saturating_sub(earlier.degrees_rotated),
+            sheets_moved: self.sheets_moved.saturating_sub(earlier.sheets_moved),
+            uptime: self.uptime.checked_sub(earlier.uptime).unwrap_or_default(),
+        }
+    }
+}
+
+/// How fast a unit wears, as the usage it takes until maintenance is due.
+///
+/// Every use wears the unit by its share of the limit, the shares of all
+/// limits add up. Limits left out do not wear the unit.
+#[derive(PartialEq, Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WearModel {
+    plots: Option<u64>,
+    turns: Option<u64>,
+    degrees_rotated: Option<u64>,
+    sheets_moved: Option<u64>,
+    uptime_hours: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum WearError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid { limit: &'static str, reason: String },
+}
+
+impl Display for WearError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WearError::Io(e) => write!(f, "Could not read wear model: {}", e),
+            WearError::Parse(e) => write!(f, "Could not parse wear model: {}", e),
+            WearError::Invalid { limit, reason } => {
+                write!(f, "Invalid limit {}: {}", limit, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WearError {}
+
+impl From<std::io::Error> for WearError {
+    fn from(e: std::io::Error) -> Self {
+        WearError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for WearError {
+    fn from(e: toml::de::Error) -> Self {
+        WearError::Parse(e)
+    }
+}
+
+impl WearModel {
+    /// Never wears, maintenance is never due
+    pub fn none() -> WearModel {
+        WearModel::default()
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<WearModel, WearError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Wear caused by `usage`, maintenance is due from 1
+    pub fn wear(&self, usage: &Usage) -> f64 {
+        let share = |used: u64, limit: Option<u64>| match limit {
+            Some(limit) => used as f64 / limit as f64,
+            None => 0.0,
+        };
+        share(usage.plots, self.plots)
+            + share(usage.turns, self.turns)
+            + share(usage.degrees_rotated, self.degrees_rotated)
+            + share(usage.sheets_moved, self.sheets_moved)
+            + match self.uptime_hours {
+                Some(hours) => usage.uptime.as_secs_f64() / 3600.0 / hours,
+                None => 0.0,
+            }
+    }
+
+    fn validate(&self) -> Result<(), WearError> {
+        let counts = [
+            ("plots", self.plots),
+            ("turns", self.turns),
+            ("degrees_rotated", self.degrees_rotated),
+            ("sheets_moved", self.sheets_moved),
+        ];
+        for &(limit, value) in counts.iter() {
+            if value == Some(0) {
+                return Err(WearError::Invalid {
+                    limit,
+                    reason: "must be positive, got 0".to_owned(),
+                });
+            }
+        }
+        match self.uptime_hours {
+            Some(hours) if hours.is_nan() || hours <= 0.0 => Err(WearError::Invalid {
+                limit: "uptime_hours",
+                reason: format!("must be positive, got {}", hours),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for WearModel {
+    type Err = WearError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let model: WearModel = toml::from_str(s)?;
+        model.validate()?;
+        Ok(model)
+    }
+}
+
+/// Wear of a unit since it last left maintenance
+pub(crate) struct Wear {
+    model: WearModel,
+    /// Usage when the unit last left maintenance
+    serviced: Mutex<Usage>,
+}
+
+impl Wear {
+    pub fn new(model: WearModel) -> Wear {
+        Wear {
+            model,
+            serviced: Mutex::new(Usage::default()),
+        }
+    }
+
+    /// The unit's `counters` with its `uptime` in whole seconds, so statuses
+    /// only change once a second
+    pub fn usage(&self, counters: Usage, uptime: Duration) -> Usage {
+        Usage {
+            uptime: Duration::from_secs(uptime.as_secs()),
+            ..counters
+        }
+    }
+
+    /// Wear since the unit was last serviced, given its current `usage`
+    pub fn wear(&self, usage: &Usage) -> f64 {
+        let serviced = self.serviced.lock().unwrap_or_else(PoisonError::into_inner);
+        self.model.wear(&usage.since(&serviced))
+    }
+
+    /// Starts wearing anew from the unit's current `usage`
+    pub fn serviced(&self, usage: Usage) {
+        *self.serviced.lock().unwrap_or_else(PoisonError::into_inner) = usage;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wear() {
+        let model: WearModel = "plots = 1000\ndegrees_rotated = 36000\nuptime_hours = 10.0"
+            .parse()
+            .unwrap();
+        let usage = Usage {
+            plots: 500,
+            turns: 200,
+            degrees_rotated: 9000,
+            sheets_moved: 700,
+            uptime: Duration::from_secs(3600),
+        };
+        // Turns and sheets have no limit
+        assert!((model.wear(&usage) - 0.85).abs() < 1e-9);
+        assert_eq!(0.0, WearModel::none().wear(&usage));
+
+        let earlier = Usage {
+            plots: 400,
+            ..usage
+        };
+        assert!((model.wear(&usage.since(&earlier)) - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn invalid() {
+        match "turns = 0".parse::<WearModel>() {
+            Err(WearError::Invalid { limit, .. }) => assert_eq!("turns", limit),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert!("uptime_hours = -1.0".parse::<WearModel>().is_err());
+        assert!("plots = \"many\"".parse::<WearModel>().is_err());
+    }
+
+    #[test]
+    fn serviced() {
+        let wear = Wear::new("sheets_moved = 10".parse().unwrap());
+        let usage = Usage {
+            sheets_moved: 12,
+            ..Usage::default()
+        };
+        let uptime = Duration::from_millis(2500);
+        assert!((wear.wear(&wear.usage(usage, uptime)) - 1.2).abs() < 1e-9);
+        assert_eq!(2, wear.usage(usage, uptime).uptime.as_secs());
+        wear.serviced(wear.usage(usage, uptime));
+        assert_eq!(0.0, wear.wear(&wear.usage(usage, uptime)));
+    }
+
+    #[test]
+    fn example_wear() {
+        WearModel::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/wear.toml")).unwrap();
+    }
+}
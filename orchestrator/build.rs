@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tonic_build::configure()
+        .build_client(false)
+        .out_dir("src/server")
+        .compile(&["../protos/fiab.proto"], &["../protos"])?;
+    Ok(())
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use tracing::warn;
+
+use factory_functional_units::{PlotterFunction, Sheet};
+
+/// A line of the journal
+#[derive(PartialEq, Debug, Clone)]
+pub enum Entry {
+    /// An order was accepted
+    Order {
+        order_id: u32,
+        customer: String,
+        functions: Vec<PlotterFunction>,
+    },
+    /// The first `step` operations of the order's plan are done. `sheet` is
+    /// the sheet the order moves, once it left the input stack.
+    Step {
+        order_id: u32,
+        step: usize,
+        sheet: Option<String>,
+    },
+    /// The sender of the handover at `step` pushed `sheet`, which is on its
+    /// way to the receiver. Recorded before the receiver pulls it, as the
+    /// sheet is only known to the orchestrator until then.
+    Handover {
+        order_id: u32,
+        step: usize,
+        sheet: Sheet,
+    },
+    /// The order ended in the given state
+    Finished { order_id: u32, state: String },
+}
+
+impl Entry {
+    pub fn order_id(&self) -> u32 {
+        match self {
+            Entry::Order { order_id, .. } => *order_id,
+            Entry::Step { order_id, .. } => *order_id,
+            Entry::Handover { order_id, .. } => *order_id,
+            Entry::Finished { order_id, .. } => *order_id,
+        }
+    }
+}
+
+fn function_list(functions: &[PlotterFunction]) -> String {
+    let names: Vec<String> = functions.iter().map(|f| f.to_string()).collect();
+    names.join(",")
+}
+
+fn parse_functions(names: &str) -> Result<Vec<PlotterFunction>, String> {
+    names
+        .split(',')
+        .filter(|f| !f.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Tabs and line breaks would break the line format
+fn field(value: &str) -> String {
+    value.replace(&['\t', '\n', '\r'][..], " ")
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Entry::Order {
+                order_id,
+                customer,
+                functions,
+            } => write!(
+                f,
+                "order\t{}\t{}\t{}",
+                order_id,
+                field(customer),
+                function_list(functions)
+            ),
+            Entry::Step {
+                order_id,
+                step,
+                sheet,
+            } => write!(
+                f,
+                "step\t{}\t{}\t{}",
+                order_id,
+                step,
+                sheet.as_ref().map(|s| field(s)).unwrap_or_default()
+            ),
+            Entry::Handover {
+                order_id,
+                step,
+                sheet,
+            } => write!(
+                f,
+                "handover\t{}\t{}\t{}\t{}",
+                order_id,
+                step,
+                field(sheet.id()),
+                function_list(sheet.functions())
+            ),
+            Entry::Finished { order_id, state } => {
+                write!(f, "finished\t{}\t{}", order_id, field(state))
+            }
+        }
+    }
+}
+
+impl FromStr for Entry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split('\t').collect();
+        let order_id = |i: usize| -> Result<u32, String> {
+            fields
+                .get(i)
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| format!("Invalid order id in '{}'", s))
+        };
+        let step = |i: usize| -> Result<usize, String> {
+            fields
+                .get(i)
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| format!("Invalid step in '{}'", s))
+        };
+        match (fields[0], fields.len()) {
+            ("order", 4) => Ok(Entry::Order {
+                order_id: order_id(1)?,
+                customer: fields[2].to_owned(),
+                functions: parse_functions(fields[3])?,
+            }),
+            ("step", 4) => Ok(Entry::Step {
+                order_id: order_id(1)?,
+                step: step(2)?,
+                sheet: Some(fields[3].to_owned()).filter(|s| !s.is_empty()),
+            }),
+            ("handover", 5) => Ok(Entry::Handover {
+                order_id: order_id(1)?,
+                step: step(2)?,
+                sheet: Sheet::with_functions(fields[3], parse_functions(fields[4])?),
+            }),
+            ("finished", 3) => Ok(Entry::Finished {
+                order_id: order_id(1)?,
+                state: fields[2].to_owned(),
+            }),
+            _ => Err(format!("Unknown journal entry '{}'", s)),
+        }
+    }
+}
+
+/// Order that was not finished when the journal was last written
+#[derive(PartialEq, Debug)]
+pub struct Unfinished {
+    pub order_id: u32,
+    pub customer: String,
+    pub functions: Vec<PlotterFunction>,
+    /// Number of operations known to be done
+    pub step: usize,
+    pub sheet: Option<String>,
+    /// Sheet pushed by the sender of the handover at `step`, which may not
+    /// have reached the receiver
+    pub in_transit: Option<Sheet>,
+}
+
+/// Orders accepted but not finished, in the order they were accepted
+pub fn unfinished(entries: &[Entry]) -> Vec<Unfinished> {
+    let mut orders: Vec<Unfinished> = Vec::new();
+    let mut index = HashMap::new();
+    for entry in entries {
+        match entry {
+            Entry::Order {
+                order_id,
+                customer,
+                functions,
+            } => {
+                index.insert(*order_id, orders.len());
+                orders.push(Unfinished {
+                    order_id: *order_id,
+                    customer: customer.clone(),
+                    functions: functions.clone(),
+                    step: 0,
+                    sheet: None,
+                    in_transit: None,
+                });
+            }
+            Entry::Step {
+                order_id,
+                step,
+                sheet,
+            } => {
+                if let Some(&i) = index.get(order_id) {
+                    orders[i].step = *step;
+                    orders[i].sheet = sheet.clone();
+                    orders[i].in_transit = None;
+                }
+            }
+            Entry::Handover {
+                order_id,
+                step,
+                sheet,
+            } => {
+                if let Some(&i) = index.get(order_id) {
+                    orders[i].step = *step;
+                    orders[i].sheet = Some(sheet.id().to_owned());
+                    orders[i].in_transit = Some(sheet.clone());
+                }
+            }
+            Entry::Finished { order_id, .. } => {
+                index.remove(order_id);
+            }
+        }
+    }
+    orders
+        .into_iter()
+        .filter(|o| index.contains_key(&o.order_id))
+        .collect()
+}
+
+/// Append-only record of orders and their progress, one entry per line
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    /// Opens the journal for appending and returns the entries written so
+    /// far. A torn last line of a crashed process is skipped.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<(Journal, Vec<Entry>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let mut entries = Vec::new();
+        for line in content.lines().filter(|l| !l.is_empty()) {
+            match line.parse() {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("Skipping journal line: {}", e),
+            }
+        }
+        if !content.is_empty() && !content.ends_with('\n') {
+            writeln!(file)?;
+        }
+        Ok((Journal { file }, entries))
+    }
+
+    /// Writes the entry and waits until it is on disk
+    pub fn append(&mut self, entry: &Entry) -> std::io::Result<()> {
+        writeln!(self.file, "{}", entry)?;
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            Entry::Order {
+                order_id: 1,
+                customer: "alice".to_owned(),
+                functions: vec![PlotterFunction::DrawRed, PlotterFunction::DrawBlue],
+            },
+            Entry::Order {
+                order_id: 2,
+                customer: "bob".to_owned(),
+                functions: vec![],
+            },
+            Entry::Step {
+                order_id: 1,
+                step: 3,
+                sheet: Some("input-1".to_owned()),
+            },
+            Entry::Step {
+                order_id: 2,
+                step: 1,
+                sheet: None,
+            },
+            Entry::Finished {
+                order_id: 2,
+                state: "Done".to_owned(),
+            },
+            Entry::Handover {
+                order_id: 1,
+                step: 3,
+                sheet: Sheet::with_functions(
+                    "input-1",
+                    vec![PlotterFunction::DrawRed, PlotterFunction::DrawBlue],
+                ),
+            },
+        ]
+    }
+
+    #[test]
+    fn line_roundtrip() {
+        for entry in entries() {
+            assert_eq!(Ok(entry.clone()), entry.to_string().parse());
+        }
+    }
+
+    #[test]
+    fn customer_with_tab() {
+        let entry = Entry::Order {
+            order_id: 1,
+            customer: "a\tb".to_owned(),
+            functions: vec![],
+        };
+        match entry.to_string().parse() {
+            Ok(Entry::Order { customer, .. }) => assert_eq!("a b", customer),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert!("order\t1\talice".parse::<Entry>().is_err());
+        assert!("order\tx\talice\t".parse::<Entry>().is_err());
+        assert!("order\t1\talice\tDrawPurple".parse::<Entry>().is_err());
+        assert!("step\t1\t2".parse::<Entry>().is_err());
+        assert!("unknown".parse::<Entry>().is_err());
+    }
+
+    #[test]
+    fn unfinished_orders() {
+        assert_eq!(
+            vec![Unfinished {
+                order_id: 1,
+                customer: "alice".to_owned(),
+                functions: vec![PlotterFunction::DrawRed, PlotterFunction::DrawBlue],
+                step: 3,
+                sheet: Some("input-1".to_owned()),
+                in_transit: Some(Sheet::with_functions(
+                    "input-1",
+                    vec![PlotterFunction::DrawRed, PlotterFunction::DrawBlue],
+                )),
+            }],
+            unfinished(&entries())
+        );
+    }
+
+    #[test]
+    fn unfinished_handover() {
+        let sheet = Sheet::with_functions("input-1", vec![PlotterFunction::DrawRed]);
+        let order = Entry::Order {
+            order_id: 1,
+            customer: "alice".to_owned(),
+            functions: vec![PlotterFunction::DrawRed],
+        };
+        // Crashed during the first handover, before any step was recorded
+        let handover = Entry::Handover {
+            order_id: 1,
+            step: 0,
+            sheet: sheet.clone(),
+        };
+        let crashed = unfinished(&[order.clone(), handover.clone()]);
+        assert_eq!(0, crashed[0].step);
+        assert_eq!(Some("input-1".to_owned()), crashed[0].sheet);
+        assert_eq!(Some(sheet.clone()), crashed[0].in_transit);
+
+        // The step after the handover was recorded
+        let step = Entry::Step {
+            order_id: 1,
+            step: 1,
+            sheet: Some("input-1".to_owned()),
+        };
+        let crashed = unfinished(&[order, handover, step]);
+        assert_eq!(1, crashed[0].step);
+        assert_eq!(None, crashed[0].in_transit);
+    }
+
+    #[test]
+    fn append_and_reopen() {
+        let path = std::env::temp_dir().join(format!("orders-{}.journal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let (mut journal, existing) = Journal::open(&path).unwrap();
+            assert!(existing.is_empty());
+            for entry in entries() {
+                journal.append(&entry).unwrap();
+            }
+        }
+        // A crash in the middle of a write
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"step\t1")
+            .unwrap();
+
+        let (mut journal, reopened) = Journal::open(&path).unwrap();
+        assert_eq!(entries(), reopened);
+
+        let finished = Entry::Finished {
+            order_id: 1,
+            state: "Done".to_owned(),
+        };
+        journal.append(&finished).unwrap();
+        let (_, reopened) = Journal::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(Some(&finished), reopened.last());
+    }
+}
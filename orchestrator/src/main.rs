@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use clap::{value_t, App, Arg};
+use tonic::transport::Server;
+use tracing::info;
+
+use factory_functional_units::{Layout, SpanExporter};
+
+use crate::journal::Journal;
+
+use crate::server::{OrderServiceServer, OrderServiceState};
+
+mod journal;
+mod planner;
+mod queue;
+mod reservation;
+mod server;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = App::new("orchestrator")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .value_name("PORT")
+                .help("Sets the port the order service listens to")
+                .default_value("5010"),
+        )
+        .arg(
+            Arg::with_name("layout")
+                .short("l")
+                .long("layout")
+                .value_name("FILE")
+                .help("Layout of the shop floor with the addresses of all units")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("journal")
+                .short("j")
+                .long("journal")
+                .value_name("FILE")
+                .help("Journal recording orders and their progress to resume them after a restart")
+                .default_value("orders.journal"),
+        )
+        .arg(
+            Arg::with_name("step-duration")
+                .long("step-duration")
+                .value_name("MILLIS")
+                .help("Time an order books its units for per operation")
+                .default_value("500"),
+        )
+        .arg(
+            Arg::with_name("max-wait")
+                .long("max-wait")
+                .value_name("SECS")
+                .help("Longest time an order waits for its units before it is rejected")
+                .default_value("30"),
+        )
+        .arg(
+            Arg::with_name("max-orders")
+                .long("max-orders")
+                .value_name("COUNT")
+                .help("Number of orders running at the same time, others are queued")
+                .default_value("4"),
+        )
+        .arg(
+            Arg::with_name("priority")
+                .long("priority")
+                .value_name("CUSTOMER=LEVEL")
+                .help("Priority of a customer's orders, higher levels run first (default 0)")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|v| parse_priority(&v).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("trace-file")
+                .long("trace-file")
+                .value_name("FILE")
+                .help("Appends a span for every order to FILE, as OTLP JSON lines")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    tracing_subscriber::fmt().with_ansi(false).init();
+
+    let port = matches.value_of("port").unwrap();
+    let layout = Layout::from_file(matches.value_of("layout").unwrap())?;
+    info!("Loaded layout with {} units", layout.units().len());
+    let step_duration = Duration::from_millis(value_t!(matches, "step-duration", u64)?);
+    let max_wait = Duration::from_secs(value_t!(matches, "max-wait", u64)?);
+    let max_orders = value_t!(matches, "max-orders", usize)?;
+    let mut priorities = HashMap::new();
+    for value in matches.values_of("priority").into_iter().flatten() {
+        let (customer, level) = parse_priority(value)?;
+        priorities.insert(customer, level);
+    }
+
+    let (journal, entries) = Journal::open(matches.value_of("journal").unwrap())?;
+    info!("Read {} journal entries", entries.len());
+
+    let addr = format!("0.0.0.0:{}", port).parse()?;
+    info!("Running order service and binding to {}", addr);
+    let mut orders = OrderServiceState::new(
+        layout,
+        step_duration,
+        max_wait,
+        max_orders,
+        priorities,
+        journal,
+    );
+    if let Some(path) = matches.value_of("trace-file") {
+        orders = orders.with_exporter(SpanExporter::to_file(path)?);
+    }
+    orders.resume(&entries);
+    Server::builder()
+        .add_service(OrderServiceServer::new(orders))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+fn parse_priority(value: &str) -> Result<(String, u32), String> {
+    let mut parts = value.rsplitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(level), Some(customer)) => level
+            .parse()
+            .map(|level| (customer.to_owned(), level))
+            .map_err(|_| format!("Invalid priority level '{}'", level)),
+        _ => Err(format!("Expected CUSTOMER=LEVEL, got '{}'", value)),
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+
+use factory_functional_units::{Layout, Orientation, PlotterFunction, UnitKind, UnitLayout};
+
+/// A single call on a functional unit
+#[derive(PartialEq, Debug, Clone)]
+pub enum Operation {
+    TurnTo {
+        conveyor: String,
+        target: Orientation,
+    },
+    /// Moves the sheet from one unit to the adjacent other
+    Handover { from: String, to: String },
+    Plot {
+        plotter: String,
+        function: PlotterFunction,
+    },
+}
+
+impl Operation {
+    /// Units the operation is called on
+    pub fn units(&self) -> Vec<&str> {
+        match self {
+            Operation::TurnTo { conveyor, .. } => vec![conveyor],
+            Operation::Handover { from, to } => vec![from, to],
+            Operation::Plot { plotter, .. } => vec![plotter],
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::TurnTo { conveyor, target } => write!(f, "{}.turn_to({})", conveyor, target),
+            Operation::Handover { from, to } => write!(f, "{} -> {}", from, to),
+            Operation::Plot { plotter, function } => write!(f, "{}.plot({})", plotter, function),
+        }
+    }
+}
+
+/// Reason why no route exists for an order
+#[derive(PartialEq, Debug)]
+pub enum PlanError {
+    NoInputStack,
+    NoOutputStack,
+    NoPlotter(PlotterFunction),
+    Unreachable,
+}
+
+impl Display for PlanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlanError::NoInputStack => write!(f, "No input stack on the floor"),
+            PlanError::NoOutputStack => write!(f, "No output stack on the floor"),
+            PlanError::NoPlotter(function) => write!(f, "No plotter offers {}", function),
+            PlanError::Unreachable => write!(f, "No route connects the required units"),
+        }
+    }
+}
+
+/// Computes routes for sheets over a [`Layout`].
+///
+/// A route starts at an input stack, visits a plotter for every requested
+/// function in order and ends at an output stack. Sheets only pass through
+/// conveyors on their way between these units. Of all possible routes the
+/// one with the fewest handovers is chosen.
+pub struct Planner<'a> {
+    layout: &'a Layout,
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(layout: &'a Layout) -> Planner<'a> {
+        Planner { layout }
+    }
+
+    pub fn plan(&self, functions: &[PlotterFunction]) -> Result<Vec<Operation>, PlanError> {
+        let route = self.route(functions)?;
+        let mut ops = Vec::new();
+        let mut facing = HashMap::new();
+        for (i, segment) in route.iter().enumerate() {
+            for hop in segment.windows(2) {
+                self.transfer(&mut ops, &mut facing, hop[0], hop[1]);
+            }
+            if let Some(&function) = functions.get(i) {
+                ops.push(Operation::Plot {
+                    plotter: segment[segment.len() - 1].id().to_owned(),
+                    function,
+                });
+            }
+        }
+        Ok(ops)
+    }
+
+    /// Units of the shortest route, split into one segment per visited plotter
+    /// and a final one to the output stack
+    fn route(&self, functions: &[PlotterFunction]) -> Result<Vec<Vec<&'a UnitLayout>>, PlanError> {
+        let inputs = self.units_of(UnitKind::InputStack);
+        if inputs.is_empty() {
+            return Err(PlanError::NoInputStack);
+        }
+        let mut stages = Vec::new();
+        for &function in functions {
+            let plotters: Vec<_> = self
+                .units_of(UnitKind::Plotter)
+                .into_iter()
+                .filter(|p| p.functions().contains(&function))
+                .collect();
+            if plotters.is_empty() {
+                return Err(PlanError::NoPlotter(function));
+            }
+            stages.push(plotters);
+        }
+        let outputs = self.units_of(UnitKind::OutputStack);
+        if outputs.is_empty() {
+            return Err(PlanError::NoOutputStack);
+        }
+        stages.push(outputs);
+
+        // Shortest route ending at each candidate of a stage, with the
+        // segments leading there
+        let mut best: Vec<(&UnitLayout, Vec<Vec<&UnitLayout>>)> =
+            inputs.into_iter().map(|i| (i, Vec::new())).collect();
+        for stage in stages {
+            let mut next: Vec<(&UnitLayout, Vec<Vec<&UnitLayout>>)> = Vec::new();
+            for target in stage {
+                let mut shortest: Option<Vec<Vec<&UnitLayout>>> = None;
+                for (start, segments) in &best {
+                    if let Some(path) = self.path(start, target) {
+                        let cost = Self::cost(segments) + path.len();
+                        let shorter = match &shortest {
+                            Some(s) => cost < Self::cost(s),
+                            None => true,
+                        };
+                        if shorter {
+                            let mut segments = segments.clone();
+                            segments.push(path);
+                            shortest = Some(segments);
+                        }
+                    }
+                }
+                if let Some(segments) = shortest {
+                    next.push((target, segments));
+                }
+            }
+            if next.is_empty() {
+                return Err(PlanError::Unreachable);
+            }
+            best = next;
+        }
+
+        let (_, segments) = best
+            .into_iter()
+            .min_by_key(|(_, segments)| Self::cost(segments))
+            .ok_or(PlanError::Unreachable)?;
+        Ok(segments)
+    }
+
+    fn cost(segments: &[Vec<&UnitLayout>]) -> usize {
+        segments.iter().map(|s| s.len()).sum()
+    }
+
+    fn units_of(&self, kind: UnitKind) -> Vec<&'a UnitLayout> {
+        self.layout
+            .units()
+            .iter()
+            .filter(|u| u.kind() == kind)
+            .collect()
+    }
+
+    /// Shortest path from `from` to `to` passing only through conveyors
+    fn path(&self, from: &'a UnitLayout, to: &'a UnitLayout) -> Option<Vec<&'a UnitLayout>> {
+        if from.id() == to.id() {
+            return Some(vec![from]);
+        }
+        let mut previous: HashMap<&str, &UnitLayout> = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(from.id());
+        queue.push_back(from);
+        while let Some(unit) = queue.pop_front() {
+            if unit.id() != from.id() && unit.kind() != UnitKind::Conveyor {
+                continue;
+            }
+            for (_, id) in unit.connections() {
+                let neighbour = match self.layout.unit(id) {
+                    Some(neighbour) => neighbour,
+                    None => continue,
+                };
+                if !visited.insert(neighbour.id()) {
+                    continue;
+                }
+                previous.insert(neighbour.id(), unit);
+                if neighbour.id() == to.id() {
+                    let mut path = vec![neighbour];
+                    let mut current = neighbour;
+                    while let Some(&prev) = previous.get(current.id()) {
+                        path.push(prev);
+                        current = prev;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                queue.push_back(neighbour);
+            }
+        }
+        None
+    }
+
+    /// Hands the sheet over from `from` to the adjacent `to`, turning the
+    /// conveyors involved towards each other first
+    fn transfer(
+        &self,
+        ops: &mut Vec<Operation>,
+        facing: &mut HashMap<String, Orientation>,
+        from: &UnitLayout,
+        to: &UnitLayout,
+    ) {
+        let side = from
+            .connections()
+            .find(|&(_, id)| id == to.id())
+            .map(|(side, _)| side)
+            .expect("Route only contains adjacent units");
+        for &(unit, target) in &[(from, side), (to, side.inverse())] {
+            if unit.kind() == UnitKind::Conveyor && facing.get(unit.id()) != Some(&target) {
+                facing.insert(unit.id().to_owned(), target);
+                ops.push(Operation::TurnTo {
+                    conveyor: unit.id().to_owned(),
+                    target,
+                });
+            }
+        }
+        ops.push(Operation::Handover {
+            from: from.id().to_owned(),
+            to: to.id().to_owned(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> Layout {
+        Layout::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../factory_functional_units/layout.toml"
+        ))
+        .unwrap()
+    }
+
+    fn turn_to(conveyor: &str, target: Orientation) -> Operation {
+        Operation::TurnTo {
+            conveyor: conveyor.to_owned(),
+            target,
+        }
+    }
+
+    fn handover(from: &str, to: &str) -> Operation {
+        Operation::Handover {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        }
+    }
+
+    fn plot(plotter: &str, function: PlotterFunction) -> Operation {
+        Operation::Plot {
+            plotter: plotter.to_owned(),
+            function,
+        }
+    }
+
+    #[test]
+    fn without_functions() {
+        let layout = layout();
+        let ops = Planner::new(&layout).plan(&[]).unwrap();
+        assert_eq!(
+            vec![
+                turn_to("conv1", Orientation::West),
+                handover("input", "conv1"),
+                turn_to("conv1", Orientation::East),
+                turn_to("conv2", Orientation::West),
+                handover("conv1", "conv2"),
+                turn_to("conv2", Orientation::East),
+                handover("conv2", "output"),
+            ],
+            ops
+        );
+    }
+
+    #[test]
+    fn with_functions() {
+        let layout = layout();
+        let ops = Planner::new(&layout)
+            .plan(&[PlotterFunction::DrawGreen, PlotterFunction::DrawGreen])
+            .unwrap();
+        assert_eq!(
+            vec![
+                turn_to("conv1", Orientation::West),
+                handover("input", "conv1"),
+                turn_to("conv1", Orientation::South),
+                handover("conv1", "plotter2"),
+                plot("plotter2", PlotterFunction::DrawGreen),
+                plot("plotter2", PlotterFunction::DrawGreen),
+                handover("plotter2", "conv1"),
+                turn_to("conv1", Orientation::East),
+                turn_to("conv2", Orientation::West),
+                handover("conv1", "conv2"),
+                turn_to("conv2", Orientation::East),
+                handover("conv2", "output"),
+            ],
+            ops
+        );
+    }
+
+    #[test]
+    fn back_and_forth() {
+        let layout = layout();
+        let ops = Planner::new(&layout)
+            .plan(&[PlotterFunction::DrawYellow, PlotterFunction::DrawRed])
+            .unwrap();
+        let plotters: Vec<_> = ops
+            .iter()
+            .filter_map(|op| match op {
+                Operation::Plot { plotter, .. } => Some(plotter.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec!["plotter4", "plotter1"], plotters);
+        assert_eq!(&handover("conv2", "output"), ops.last().unwrap());
+    }
+
+    #[test]
+    fn missing_function() {
+        let layout: Layout = r#"
+            [[unit]]
+            id = "input"
+            kind = "InputStack"
+            address = "http://localhost:5004"
+            east = "output"
+
+            [[unit]]
+            id = "output"
+            kind = "OutputStack"
+            address = "http://localhost:5005"
+            west = "input"
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            Err(PlanError::NoPlotter(PlotterFunction::DrawRed)),
+            Planner::new(&layout).plan(&[PlotterFunction::DrawRed])
+        );
+        assert_eq!(
+            vec![handover("input", "output")],
+            Planner::new(&layout).plan(&[]).unwrap()
+        );
+    }
+
+    #[test]
+    fn unreachable() {
+        let layout: Layout = r#"
+            [[unit]]
+            id = "input"
+            kind = "InputStack"
+            address = "http://localhost:5004"
+            east = "conv1"
+
+            [[unit]]
+            id = "conv1"
+            kind = "Conveyor"
+            address = "http://localhost:5006"
+            west = "input"
+
+            [[unit]]
+            id = "output"
+            kind = "OutputStack"
+            address = "http://localhost:5005"
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(Err(PlanError::Unreachable), Planner::new(&layout).plan(&[]));
+    }
+
+    #[test]
+    fn no_stacks() {
+        let layout: Layout = "".parse().unwrap();
+        assert_eq!(
+            Err(PlanError::NoInputStack),
+            Planner::new(&layout).plan(&[])
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+#[derive(Debug)]
+struct Waiting {
+    order_id: u32,
+    customer: String,
+    arrival: u64,
+}
+
+/// Decides which waiting order runs next.
+///
+/// Orders of customers with a higher priority go first. Among customers of
+/// the same priority the one served least recently goes first, so a
+/// customer placing many orders cannot starve the others. Orders of the
+/// same customer run in the order they arrived.
+pub struct OrderQueue {
+    waiting: Vec<Waiting>,
+    priorities: HashMap<String, u32>,
+    /// Number of the start a customer's latest order got
+    last_started: HashMap<String, u64>,
+    starts: u64,
+    arrivals: u64,
+    running: usize,
+    max_running: usize,
+}
+
+impl OrderQueue {
+    /// At most `max_running` orders run at the same time. Customers missing
+    /// in `priorities` have priority 0.
+    pub fn new(max_running: usize, priorities: HashMap<String, u32>) -> OrderQueue {
+        OrderQueue {
+            waiting: Vec::new(),
+            priorities,
+            last_started: HashMap::new(),
+            starts: 0,
+            arrivals: 0,
+            running: 0,
+            max_running,
+        }
+    }
+
+    pub fn push(&mut self, order_id: u32, customer: &str) {
+        self.arrivals += 1;
+        self.waiting.push(Waiting {
+            order_id,
+            customer: customer.to_owned(),
+            arrival: self.arrivals,
+        });
+    }
+
+    /// Takes the next order to run, if one is waiting and fewer than the
+    /// maximum are running
+    pub fn pop(&mut self) -> Option<u32> {
+        if self.running >= self.max_running {
+            return None;
+        }
+        let next = self.ranked().first().map(|w| w.order_id)?;
+        let index = self.waiting.iter().position(|w| w.order_id == next)?;
+        let order = self.waiting.remove(index);
+        self.starts += 1;
+        self.last_started.insert(order.customer, self.starts);
+        self.running += 1;
+        Some(order.order_id)
+    }
+
+    /// Frees the slot of a running order
+    pub fn finish(&mut self) {
+        self.running = self.running.saturating_sub(1);
+    }
+
+    /// Waiting orders, the one running next first
+    pub fn positions(&self) -> Vec<u32> {
+        self.ranked().iter().map(|w| w.order_id).collect()
+    }
+
+    fn ranked(&self) -> Vec<&Waiting> {
+        let mut ranked: Vec<&Waiting> = self.waiting.iter().collect();
+        ranked.sort_by_key(|w| {
+            (
+                std::cmp::Reverse(self.priorities.get(&w.customer).copied().unwrap_or(0)),
+                self.last_started.get(&w.customer).copied().unwrap_or(0),
+                w.arrival,
+            )
+        });
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &mut OrderQueue) -> Vec<u32> {
+        let mut order = Vec::new();
+        while let Some(id) = queue.pop() {
+            order.push(id);
+            queue.finish();
+        }
+        order
+    }
+
+    #[test]
+    fn first_come_first_served() {
+        let mut queue = OrderQueue::new(1, HashMap::new());
+        queue.push(1, "alice");
+        queue.push(2, "alice");
+        queue.push(3, "alice");
+        assert_eq!(vec![1, 2, 3], drain(&mut queue));
+    }
+
+    #[test]
+    fn max_running() {
+        let mut queue = OrderQueue::new(2, HashMap::new());
+        queue.push(1, "alice");
+        queue.push(2, "bob");
+        queue.push(3, "carol");
+        assert_eq!(Some(1), queue.pop());
+        assert_eq!(Some(2), queue.pop());
+        assert_eq!(None, queue.pop());
+        assert_eq!(vec![3], queue.positions());
+
+        queue.finish();
+        assert_eq!(Some(3), queue.pop());
+    }
+
+    #[test]
+    fn priority() {
+        let mut priorities = HashMap::new();
+        priorities.insert("bob".to_owned(), 2);
+        let mut queue = OrderQueue::new(1, priorities);
+        queue.push(1, "alice");
+        queue.push(2, "alice");
+        queue.push(3, "bob");
+        assert_eq!(vec![3, 1, 2], queue.positions());
+        assert_eq!(vec![3, 1, 2], drain(&mut queue));
+    }
+
+    #[test]
+    fn fairness() {
+        let mut queue = OrderQueue::new(1, HashMap::new());
+        queue.push(1, "alice");
+        queue.push(2, "alice");
+        queue.push(3, "alice");
+        queue.push(4, "bob");
+        queue.push(5, "bob");
+        queue.push(6, "carol");
+        assert_eq!(vec![1, 4, 6, 2, 5, 3], drain(&mut queue));
+    }
+
+    #[test]
+    fn positions_change_after_start() {
+        let mut queue = OrderQueue::new(1, HashMap::new());
+        queue.push(1, "alice");
+        queue.push(2, "alice");
+        queue.push(3, "bob");
+        assert_eq!(vec![1, 2, 3], queue.positions());
+
+        assert_eq!(Some(1), queue.pop());
+        assert_eq!(vec![3, 2], queue.positions());
+    }
+}
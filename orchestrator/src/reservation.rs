@@ -0,0 +1,207 @@
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+/// The units of an order are booked too far into the future
+#[derive(PartialEq, Debug)]
+pub struct Contention {
+    /// Time the order would have to wait for its window
+    pub wait: Duration,
+}
+
+impl Display for Contention {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Units are booked for the next {:?}", self.wait)
+    }
+}
+
+#[derive(Debug)]
+struct Reservation {
+    order_id: u32,
+    units: Vec<String>,
+    start: Instant,
+    end: Instant,
+}
+
+impl Reservation {
+    fn shares_units(&self, units: &[String]) -> bool {
+        self.units.iter().any(|u| units.contains(u))
+    }
+
+    /// End of the window. Orders running late keep their units until they
+    /// release them.
+    fn end(&self, now: Instant) -> Instant {
+        std::cmp::max(self.end, now)
+    }
+}
+
+/// Books units for orders running concurrently.
+///
+/// Every order claims the units on its route for a time window before it
+/// moves a sheet. Windows of orders sharing a unit never overlap, so
+/// an order starts once all orders booked before it on shared units have
+/// released them.
+pub struct ReservationTable {
+    reservations: Vec<Reservation>,
+    max_wait: Duration,
+}
+
+impl ReservationTable {
+    /// Orders that would have to wait longer than `max_wait` for their
+    /// window are rejected
+    pub fn new(max_wait: Duration) -> ReservationTable {
+        ReservationTable {
+            reservations: Vec::new(),
+            max_wait,
+        }
+    }
+
+    /// Books `units` for `duration` at the earliest time from `now` on when
+    /// none of them is booked by another order and returns the start of
+    /// the window
+    pub fn reserve(
+        &mut self,
+        order_id: u32,
+        units: Vec<String>,
+        duration: Duration,
+        now: Instant,
+    ) -> Result<Instant, Contention> {
+        let mut conflicting: Vec<&Reservation> = self
+            .reservations
+            .iter()
+            .filter(|r| r.shares_units(&units))
+            .collect();
+        conflicting.sort_by_key(|r| r.start);
+
+        let mut start = now;
+        for r in conflicting {
+            if r.start < start + duration && start < r.end(now) {
+                start = r.end(now);
+            }
+        }
+        let wait = start - now;
+        if wait > self.max_wait {
+            return Err(Contention { wait });
+        }
+
+        self.reservations.push(Reservation {
+            order_id,
+            units,
+            start,
+            end: start + duration,
+        });
+        Ok(start)
+    }
+
+    /// Whether all orders booked before the order on any of its units have
+    /// released them
+    pub fn ready(&self, order_id: u32) -> bool {
+        let own = match self.reservations.iter().find(|r| r.order_id == order_id) {
+            Some(own) => own,
+            None => return false,
+        };
+        !self
+            .reservations
+            .iter()
+            .any(|r| r.order_id != order_id && r.start < own.start && r.shares_units(&own.units))
+    }
+
+    pub fn release(&mut self, order_id: u32) {
+        self.reservations.retain(|r| r.order_id != order_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn units(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|&id| id.to_owned()).collect()
+    }
+
+    #[test]
+    fn disjoint_units() {
+        let now = Instant::now();
+        let mut table = ReservationTable::new(10 * SECOND);
+        assert_eq!(
+            Ok(now),
+            table.reserve(1, units(&["conv1", "plotter1"]), 5 * SECOND, now)
+        );
+        assert_eq!(
+            Ok(now),
+            table.reserve(2, units(&["conv2", "plotter3"]), 5 * SECOND, now)
+        );
+        assert!(table.ready(1));
+        assert!(table.ready(2));
+    }
+
+    #[test]
+    fn shared_unit() {
+        let now = Instant::now();
+        let mut table = ReservationTable::new(10 * SECOND);
+        table
+            .reserve(1, units(&["conv1", "plotter1"]), 5 * SECOND, now)
+            .unwrap();
+        assert_eq!(
+            Ok(now + 5 * SECOND),
+            table.reserve(2, units(&["conv1", "plotter2"]), 5 * SECOND, now)
+        );
+        assert!(!table.ready(2));
+
+        table.release(1);
+        assert!(table.ready(2));
+    }
+
+    #[test]
+    fn fills_gap() {
+        let now = Instant::now();
+        let mut table = ReservationTable::new(20 * SECOND);
+        table
+            .reserve(1, units(&["conv1"]), 2 * SECOND, now)
+            .unwrap();
+        table
+            .reserve(2, units(&["conv2"]), 5 * SECOND, now)
+            .unwrap();
+        assert_eq!(
+            Ok(now + 5 * SECOND),
+            table.reserve(3, units(&["conv1", "conv2"]), 5 * SECOND, now)
+        );
+        // Short enough for the time conv1 is free before order 3 needs it
+        assert_eq!(
+            Ok(now + 2 * SECOND),
+            table.reserve(4, units(&["conv1"]), 3 * SECOND, now)
+        );
+        assert_eq!(
+            Ok(now + 10 * SECOND),
+            table.reserve(5, units(&["conv1"]), 4 * SECOND, now)
+        );
+    }
+
+    #[test]
+    fn late_order_keeps_units() {
+        let now = Instant::now();
+        let mut table = ReservationTable::new(10 * SECOND);
+        table.reserve(1, units(&["conv1"]), SECOND, now).unwrap();
+        let later = now + 3 * SECOND;
+        assert_eq!(
+            Ok(later),
+            table.reserve(2, units(&["conv1"]), SECOND, later)
+        );
+        assert!(!table.ready(2));
+    }
+
+    #[test]
+    fn contention() {
+        let now = Instant::now();
+        let mut table = ReservationTable::new(4 * SECOND);
+        table
+            .reserve(1, units(&["conv1"]), 5 * SECOND, now)
+            .unwrap();
+        assert_eq!(
+            Err(Contention { wait: 5 * SECOND }),
+            table.reserve(2, units(&["conv1"]), 5 * SECOND, now)
+        );
+        assert!(!table.ready(2));
+    }
+}
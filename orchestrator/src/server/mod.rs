@@ -0,0 +1,724 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+use tonic::{Code, Request, Response, Status};
+use tracing::{info, warn};
+
+use factory_functional_units::{
+    handover, resume_handover, CallError, ConveyorClient, HandoverError, HealthClient,
+    InputStackClient, Layout, OutputStackClient, PlotError, PlotterClient, PlotterFunction,
+    PushOrPullError, Sheet, SheetReceiver, SheetSender, SpanExporter, SpanKind, TraceContext,
+    TraceSpan, TurnError, UnitKind, UnitLayout,
+};
+
+pub use fiab::order_service_server::OrderServiceServer;
+
+use self::fiab::order_status_update::State;
+use crate::journal::{self, Entry, Journal};
+use crate::planner::{Operation, PlanError, Planner};
+use crate::queue::OrderQueue;
+use crate::reservation::{Contention, ReservationTable};
+
+mod fiab;
+
+/// How often a waiting order checks whether its units were released
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl From<fiab::PlotterFunction> for PlotterFunction {
+    fn from(function: fiab::PlotterFunction) -> Self {
+        match function {
+            fiab::PlotterFunction::DrawRed => PlotterFunction::DrawRed,
+            fiab::PlotterFunction::DrawGreen => PlotterFunction::DrawGreen,
+            fiab::PlotterFunction::DrawBlue => PlotterFunction::DrawBlue,
+            fiab::PlotterFunction::DrawYellow => PlotterFunction::DrawYellow,
+        }
+    }
+}
+
+fn function_name(function: PlotterFunction) -> &'static str {
+    match function {
+        PlotterFunction::DrawRed => "DRAW_RED",
+        PlotterFunction::DrawGreen => "DRAW_GREEN",
+        PlotterFunction::DrawBlue => "DRAW_BLUE",
+        PlotterFunction::DrawYellow => "DRAW_YELLOW",
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum OrderError {
+    PlottingFailed,
+    TransportFailed,
+}
+
+impl From<Status> for OrderError {
+    fn from(_: Status) -> Self {
+        OrderError::TransportFailed
+    }
+}
+
+impl From<tonic::transport::Error> for OrderError {
+    fn from(_: tonic::transport::Error) -> Self {
+        OrderError::TransportFailed
+    }
+}
+
+impl From<HandoverError> for OrderError {
+    fn from(_: HandoverError) -> Self {
+        OrderError::TransportFailed
+    }
+}
+
+impl From<CallError<TurnError>> for OrderError {
+    fn from(_: CallError<TurnError>) -> Self {
+        OrderError::TransportFailed
+    }
+}
+
+impl From<CallError<PlotError>> for OrderError {
+    fn from(e: CallError<PlotError>) -> Self {
+        match e {
+            CallError::Unit(_) => OrderError::PlottingFailed,
+            CallError::Rpc(_) => OrderError::TransportFailed,
+        }
+    }
+}
+
+impl From<OrderError> for State {
+    fn from(e: OrderError) -> Self {
+        match e {
+            OrderError::PlottingFailed => State::PlottingFailed,
+            OrderError::TransportFailed => State::TransportFailed,
+        }
+    }
+}
+
+impl From<PlanError> for State {
+    fn from(_: PlanError) -> Self {
+        State::NoPathFound
+    }
+}
+
+impl From<Contention> for State {
+    fn from(_: Contention) -> Self {
+        State::PathInUseTooOften
+    }
+}
+
+#[derive(Clone)]
+enum UnitClient {
+    Plotter(PlotterClient),
+    Conveyor(ConveyorClient),
+    InputStack(InputStackClient),
+    OutputStack(OutputStackClient),
+}
+
+impl UnitClient {
+    /// Connects to a unit that reports itself as serving. Calls to it are
+    /// part of `trace` and name `caller` in the unit's history.
+    async fn connect(
+        unit: &UnitLayout,
+        trace: TraceContext,
+        caller: &str,
+    ) -> Result<UnitClient, OrderError> {
+        let addr = unit.address().to_owned();
+        let mut health = HealthClient::connect(addr.clone()).await?;
+        match health.check("").await {
+            Ok(true) => {}
+            // Units built before the health service was added
+            Err(ref e) if e.code() == Code::Unimplemented => {}
+            _ => {
+                warn!(unit = unit.id(), "Unit is not serving");
+                return Err(OrderError::TransportFailed);
+            }
+        }
+        Ok(match unit.kind() {
+            UnitKind::Plotter => UnitClient::Plotter(
+                PlotterClient::connect(addr)
+                    .await?
+                    .with_trace(trace)
+                    .with_caller(caller),
+            ),
+            UnitKind::Conveyor => UnitClient::Conveyor(
+                ConveyorClient::connect(addr)
+                    .await?
+                    .with_trace(trace)
+                    .with_caller(caller),
+            ),
+            UnitKind::InputStack => UnitClient::InputStack(
+                InputStackClient::connect(addr)
+                    .await?
+                    .with_trace(trace)
+                    .with_caller(caller),
+            ),
+            UnitKind::OutputStack => UnitClient::OutputStack(
+                OutputStackClient::connect(addr)
+                    .await?
+                    .with_trace(trace)
+                    .with_caller(caller),
+            ),
+        })
+    }
+
+    fn sender(&mut self) -> Option<&mut dyn SheetSender> {
+        match self {
+            UnitClient::Plotter(c) => Some(c),
+            UnitClient::Conveyor(c) => Some(c),
+            UnitClient::InputStack(c) => Some(c),
+            UnitClient::OutputStack(_) => None,
+        }
+    }
+
+    fn receiver(&mut self) -> Option<&mut dyn SheetReceiver> {
+        match self {
+            UnitClient::Plotter(c) => Some(c),
+            UnitClient::Conveyor(c) => Some(c),
+            UnitClient::InputStack(_) => None,
+            UnitClient::OutputStack(c) => Some(c),
+        }
+    }
+}
+
+/// Sender that records the sheet it pushed in the journal before the
+/// receiver gets it. Only a crash between the push and the record loses the
+/// sheet.
+struct JournaledSender<'a> {
+    sender: &'a mut dyn SheetSender,
+    scheduler: &'a Scheduler,
+    order_id: u32,
+    step: usize,
+}
+
+#[tonic::async_trait]
+impl SheetSender for JournaledSender<'_> {
+    async fn push(&mut self) -> Result<Sheet, CallError<PushOrPullError>> {
+        let sheet = self.sender.push().await?;
+        self.scheduler.record(&Entry::Handover {
+            order_id: self.order_id,
+            step: self.step,
+            sheet: sheet.clone(),
+        });
+        Ok(sheet)
+    }
+
+    async fn take_back(&mut self, sheet: &Sheet) -> Result<(), CallError<PushOrPullError>> {
+        self.sender.take_back(sheet).await
+    }
+
+    async fn holds(&mut self, sheet: &Sheet) -> Result<bool, Status> {
+        self.sender.holds(sheet).await
+    }
+}
+
+struct OrderRun {
+    order_id: u32,
+    functions: Vec<PlotterFunction>,
+    updates: mpsc::Sender<Result<fiab::OrderStatusUpdate, Status>>,
+    queue_position: u32,
+    /// Number of operations of the plan known to be done
+    step: usize,
+    sheet_id: Option<String>,
+    /// Sheet the journal recorded as pushed by the sender of the handover at
+    /// `step` before a restart
+    in_transit: Option<Sheet>,
+    /// Whether the order was picked up from the journal after a restart
+    resumed: bool,
+    /// Span of the whole order, the calls to the units are its children
+    trace: TraceSpan,
+    scheduler: Arc<Scheduler>,
+}
+
+impl OrderRun {
+    async fn send(&mut self, state: State, next: Option<PlotterFunction>) {
+        let update = fiab::OrderStatusUpdate {
+            order_id: self.order_id,
+            next_func: next.map(function_name).unwrap_or("").to_owned(),
+            state: state.into(),
+            queue_position: 0,
+        };
+        info!(update = ?update, "Order update");
+        // The client may have hung up; the order is still finished
+        let _ = self.updates.send(Ok(update)).await;
+    }
+
+    /// Tells the client where the order is in the queue, if that changed
+    fn queued(&mut self, position: u32) {
+        if self.queue_position == position {
+            return;
+        }
+        self.queue_position = position;
+        let update = fiab::OrderStatusUpdate {
+            order_id: self.order_id,
+            next_func: self
+                .functions
+                .first()
+                .map(|&f| function_name(f))
+                .unwrap_or("")
+                .to_owned(),
+            state: State::Queued.into(),
+            queue_position: position,
+        };
+        info!(update = ?update, "Order update");
+        // Positions are only informative, a later update supersedes a
+        // dropped one
+        let _ = self.updates.try_send(Ok(update));
+    }
+
+    fn record_step(&mut self, step: usize) {
+        self.step = step;
+        self.in_transit = None;
+        self.scheduler.record(&Entry::Step {
+            order_id: self.order_id,
+            step,
+            sheet: self.sheet_id.clone(),
+        });
+    }
+
+    /// Whether the operation at `step` was done before the orchestrator
+    /// restarted, judging by the status of the unit it was called on
+    async fn confirmed(
+        &self,
+        ops: &[Operation],
+        step: usize,
+        units: &mut HashMap<&str, UnitClient>,
+    ) -> Result<bool, OrderError> {
+        let sheet_id = match &self.sheet_id {
+            Some(id) => id.as_str(),
+            None => return Ok(false),
+        };
+        match &ops[step] {
+            Operation::TurnTo { .. } => Ok(false),
+            Operation::Handover { to, .. } => match units.get_mut(to.as_str()) {
+                Some(unit) => match unit.receiver() {
+                    Some(receiver) => Ok(receiver.holds(&Sheet::new(sheet_id)).await?),
+                    None => Ok(false),
+                },
+                None => Ok(false),
+            },
+            Operation::Plot { plotter, .. } => {
+                let mut plotted = 0;
+                for op in &ops[..step] {
+                    if let Operation::Plot { .. } = op {
+                        plotted += 1;
+                    }
+                }
+                match units.get_mut(plotter.as_str()) {
+                    Some(UnitClient::Plotter(c)) => Ok(match c.status().await?.sheet {
+                        Some(sheet) => sheet.id() == sheet_id && sheet.functions().len() > plotted,
+                        None => false,
+                    }),
+                    _ => Ok(false),
+                }
+            }
+        }
+    }
+
+    /// Finished sheet of an order whose last handover happened before a
+    /// restart
+    async fn delivered_sheet(
+        &self,
+        ops: &[Operation],
+        units: &mut HashMap<&str, UnitClient>,
+    ) -> Result<Sheet, OrderError> {
+        let sheet_id = self.sheet_id.as_ref().ok_or(OrderError::TransportFailed)?;
+        let output = match ops.last() {
+            Some(Operation::Handover { to, .. }) => to.as_str(),
+            _ => return Err(OrderError::TransportFailed),
+        };
+        match units.get_mut(output) {
+            Some(UnitClient::OutputStack(c)) => c
+                .status()
+                .await?
+                .sheets
+                .into_iter()
+                .find(|s| s.id() == sheet_id)
+                .ok_or(OrderError::TransportFailed),
+            _ => Err(OrderError::TransportFailed),
+        }
+    }
+
+    async fn execute(&mut self, layout: &Layout, ops: &[Operation]) -> Result<(), OrderError> {
+        let caller = format!("orchestrator order #{}", self.order_id);
+        let mut units = HashMap::new();
+        for op in ops {
+            for id in op.units() {
+                if !units.contains_key(id) {
+                    let unit = layout.unit(id).ok_or(OrderError::TransportFailed)?;
+                    let client = UnitClient::connect(unit, self.trace.context(), &caller).await?;
+                    units.insert(id, client);
+                }
+            }
+        }
+
+        if self.resumed
+            && self.step < ops.len()
+            && self.confirmed(ops, self.step, &mut units).await?
+        {
+            info!(
+                order_id = self.order_id,
+                "{} was done before the restart", ops[self.step]
+            );
+            self.record_step(self.step + 1);
+        }
+
+        // The sheet as it left the last unit
+        let mut sheet = None;
+        for (i, op) in ops.iter().enumerate().skip(self.step) {
+            info!(order_id = self.order_id, "{}", op);
+            match op {
+                Operation::TurnTo { conveyor, target } => match units.get_mut(conveyor.as_str()) {
+                    Some(UnitClient::Conveyor(c)) => c.turn_to(*target).await?,
+                    _ => return Err(OrderError::TransportFailed),
+                },
+                Operation::Plot { plotter, function } => {
+                    self.send(State::InProgress, Some(*function)).await;
+                    match units.get_mut(plotter.as_str()) {
+                        Some(UnitClient::Plotter(c)) => c.plot(*function).await?,
+                        _ => return Err(OrderError::TransportFailed),
+                    }
+                }
+                Operation::Handover { from, to } => {
+                    let mut sender = units
+                        .get(from.as_str())
+                        .cloned()
+                        .ok_or(OrderError::TransportFailed)?;
+                    let mut receiver = units
+                        .get(to.as_str())
+                        .cloned()
+                        .ok_or(OrderError::TransportFailed)?;
+                    let (sender, receiver) = match (sender.sender(), receiver.receiver()) {
+                        (Some(sender), Some(receiver)) => (sender, receiver),
+                        _ => return Err(OrderError::TransportFailed),
+                    };
+                    let scheduler = self.scheduler.clone();
+                    let mut sender = JournaledSender {
+                        sender,
+                        scheduler: &scheduler,
+                        order_id: self.order_id,
+                        step: i,
+                    };
+                    let res = match self.in_transit.take() {
+                        Some(pushed) => {
+                            info!(
+                                order_id = self.order_id,
+                                sheet = pushed.id(),
+                                "Resuming handover"
+                            );
+                            resume_handover(&mut sender, receiver, &pushed).await
+                        }
+                        None => handover(&mut sender, receiver).await,
+                    };
+                    match res {
+                        Ok(moved) => {
+                            self.sheet_id = Some(moved.id().to_owned());
+                            sheet = Some(moved);
+                        }
+                        Err(e) => {
+                            warn!(order_id = self.order_id, "{}", e);
+                            return Err(e.into());
+                        }
+                    }
+                }
+            }
+            self.record_step(i + 1);
+        }
+
+        let sheet = match sheet {
+            Some(sheet) => sheet,
+            None => self.delivered_sheet(ops, &mut units).await?,
+        };
+        if sheet.functions() != self.functions.as_slice() {
+            warn!(
+                order_id = self.order_id,
+                sheet = sheet.id(),
+                functions = ?sheet.functions(),
+                "Sheet got other functions than ordered"
+            );
+            return Err(OrderError::PlottingFailed);
+        }
+        info!(order_id = self.order_id, sheet = sheet.id(), "Finished");
+        Ok(())
+    }
+
+    /// Books the conveyors and plotters the operations use. Stacks hold
+    /// many sheets and are shared between orders.
+    fn reserve(&self, layout: &Layout, ops: &[Operation]) -> Result<Instant, Contention> {
+        let mut units: Vec<String> = Vec::new();
+        for op in ops {
+            for id in op.units() {
+                let booked = layout
+                    .unit(id)
+                    .filter(|u| u.kind() == UnitKind::Conveyor || u.kind() == UnitKind::Plotter)
+                    .is_some();
+                if booked && !units.iter().any(|u| u == id) {
+                    units.push(id.to_owned());
+                }
+            }
+        }
+        let duration = self.scheduler.step_duration * ops.len() as u32;
+        self.scheduler.reservations.lock().unwrap().reserve(
+            self.order_id,
+            units,
+            duration,
+            Instant::now(),
+        )
+    }
+
+    fn ready(&self) -> bool {
+        self.scheduler
+            .reservations
+            .lock()
+            .unwrap()
+            .ready(self.order_id)
+    }
+
+    fn release(&self) {
+        self.scheduler
+            .reservations
+            .lock()
+            .unwrap()
+            .release(self.order_id);
+    }
+
+    async fn run(mut self) {
+        let scheduler = self.scheduler.clone();
+        let state = self.execute_order(&scheduler.layout).await;
+        scheduler.record(&Entry::Finished {
+            order_id: self.order_id,
+            state: format!("{:?}", state),
+        });
+        self.send(state, None).await;
+        scheduler.queue.lock().unwrap().queue.finish();
+        Scheduler::dispatch(&scheduler);
+        if let Some(exporter) = &scheduler.exporter {
+            let mut trace = self.trace;
+            trace.set_attribute("order_id", self.order_id.to_string());
+            trace.set_attribute("state", format!("{:?}", state));
+            exporter.export(trace);
+        }
+    }
+
+    /// Runs the order and returns the state it ended in
+    async fn execute_order(&mut self, layout: &Layout) -> State {
+        self.send(State::Started, self.functions.first().copied())
+            .await;
+        let ops = match Planner::new(layout).plan(&self.functions) {
+            Ok(ops) => ops,
+            Err(e) => {
+                warn!(order_id = self.order_id, "{}", e);
+                return e.into();
+            }
+        };
+        match self.reserve(layout, &ops) {
+            // Units that are free now are booked from an instant ago
+            Ok(start) => info!(
+                order_id = self.order_id,
+                wait = ?start.saturating_duration_since(Instant::now()),
+                "Booked units"
+            ),
+            Err(e) => {
+                warn!(order_id = self.order_id, "{}", e);
+                return e.into();
+            }
+        }
+        while !self.ready() {
+            tokio::time::delay_for(POLL_INTERVAL).await;
+        }
+        let res = self.execute(layout, &ops).await;
+        self.release();
+        match res {
+            Ok(_) => State::Done,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+struct Queued {
+    queue: OrderQueue,
+    /// Orders waiting in the queue
+    pending: HashMap<u32, OrderRun>,
+}
+
+/// Starts queued orders as soon as the queue lets them run
+struct Scheduler {
+    layout: Arc<Layout>,
+    reservations: Mutex<ReservationTable>,
+    step_duration: Duration,
+    queue: Mutex<Queued>,
+    journal: Mutex<Journal>,
+    exporter: Option<SpanExporter>,
+}
+
+impl Scheduler {
+    /// Appends the entry to the journal. Orders keep running if it cannot
+    /// be written, they just cannot be resumed after a restart.
+    fn record(&self, entry: &Entry) {
+        if let Err(e) = self.journal.lock().unwrap().append(entry) {
+            warn!(entry = %entry, "Could not record journal entry: {}", e);
+        }
+    }
+
+    fn enqueue(scheduler: &Arc<Scheduler>, run: OrderRun, customer: &str) {
+        {
+            let mut queued = scheduler.queue.lock().unwrap();
+            queued.queue.push(run.order_id, customer);
+            queued.pending.insert(run.order_id, run);
+        }
+        Scheduler::dispatch(scheduler);
+    }
+
+    /// Starts all orders the queue lets run and tells the others their
+    /// position
+    fn dispatch(scheduler: &Arc<Scheduler>) {
+        let mut queued = scheduler.queue.lock().unwrap();
+        while let Some(order_id) = queued.queue.pop() {
+            if let Some(run) = queued.pending.remove(&order_id) {
+                tokio::spawn(run.run());
+            }
+        }
+        for (i, order_id) in queued.queue.positions().into_iter().enumerate() {
+            if let Some(run) = queued.pending.get_mut(&order_id) {
+                run.queued(i as u32 + 1);
+            }
+        }
+    }
+}
+
+pub struct OrderServiceState {
+    scheduler: Arc<Scheduler>,
+    next_order_id: AtomicU32,
+}
+
+impl OrderServiceState {
+    /// Orders book their units for `step_duration` per operation and are
+    /// rejected if they would have to wait longer than `max_wait`. At most
+    /// `max_orders` run at the same time, the others wait in a queue
+    /// ordered by the priorities of their customers. Orders and their
+    /// progress are recorded in `journal`.
+    pub fn new(
+        layout: Layout,
+        step_duration: Duration,
+        max_wait: Duration,
+        max_orders: usize,
+        priorities: HashMap<String, u32>,
+        journal: Journal,
+    ) -> OrderServiceState {
+        let scheduler = Scheduler {
+            layout: Arc::new(layout),
+            reservations: Mutex::new(ReservationTable::new(max_wait)),
+            step_duration,
+            queue: Mutex::new(Queued {
+                queue: OrderQueue::new(max_orders, priorities),
+                pending: HashMap::new(),
+            }),
+            journal: Mutex::new(journal),
+            exporter: None,
+        };
+        OrderServiceState {
+            scheduler: Arc::new(scheduler),
+            next_order_id: AtomicU32::new(1),
+        }
+    }
+
+    /// Exports a span for every order. Must be set before any order is
+    /// taken.
+    pub fn with_exporter(mut self, exporter: SpanExporter) -> OrderServiceState {
+        Arc::get_mut(&mut self.scheduler)
+            .expect("No order holds the scheduler yet")
+            .exporter = Some(exporter);
+        self
+    }
+
+    /// Queues the orders the journal records as unfinished again. They
+    /// continue from their last recorded step; nobody listens to their
+    /// updates anymore.
+    pub fn resume(&self, entries: &[Entry]) {
+        if let Some(last) = entries.iter().map(Entry::order_id).max() {
+            self.next_order_id.store(last + 1, Ordering::SeqCst);
+        }
+        for order in journal::unfinished(entries) {
+            // The trace of the call that placed the order is lost
+            let trace = TraceSpan::start("fiab.OrderService/Resume", SpanKind::Internal, None);
+            info!(
+                order_id = order.order_id,
+                customer = order.customer.as_str(),
+                step = order.step,
+                trace_id = %trace.context().trace_id(),
+                "Resuming order"
+            );
+            let (tx, _) = mpsc::channel(16);
+            let run = OrderRun {
+                order_id: order.order_id,
+                functions: order.functions,
+                updates: tx,
+                queue_position: 0,
+                step: order.step,
+                sheet_id: order.sheet,
+                in_transit: order.in_transit,
+                resumed: true,
+                trace,
+                scheduler: self.scheduler.clone(),
+            };
+            Scheduler::enqueue(&self.scheduler, run, &order.customer);
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl fiab::order_service_server::OrderService for OrderServiceState {
+    type OrderStream = mpsc::Receiver<Result<fiab::OrderStatusUpdate, Status>>;
+
+    async fn order(
+        &self,
+        req: Request<fiab::OrderRequest>,
+    ) -> Result<Response<Self::OrderStream>, Status> {
+        let parent = TraceContext::extract(req.metadata());
+        let req = req.into_inner();
+        let mut functions = Vec::with_capacity(req.functions.len());
+        for &f in &req.functions {
+            match fiab::PlotterFunction::from_i32(f) {
+                Some(function) => functions.push(function.into()),
+                None => return Err(Status::invalid_argument(format!("Unknown function {}", f))),
+            }
+        }
+
+        let order_id = self.next_order_id.fetch_add(1, Ordering::SeqCst);
+        let trace = TraceSpan::start("fiab.OrderService/Order", SpanKind::Server, parent);
+        info!(
+            order_id = order_id,
+            customer = req.customer.as_str(),
+            functions = functions.len(),
+            trace_id = %trace.context().trace_id(),
+            "Order"
+        );
+
+        self.scheduler
+            .journal
+            .lock()
+            .unwrap()
+            .append(&Entry::Order {
+                order_id,
+                customer: req.customer.clone(),
+                functions: functions.clone(),
+            })
+            .map_err(|e| Status::unavailable(format!("Could not record order: {}", e)))?;
+
+        let (tx, rx) = mpsc::channel(16);
+        let run = OrderRun {
+            order_id,
+            functions,
+            updates: tx,
+            queue_position: 0,
+            step: 0,
+            sheet_id: None,
+            in_transit: None,
+            resumed: false,
+            trace,
+            scheduler: self.scheduler.clone(),
+        };
+        Scheduler::enqueue(&self.scheduler, run, &req.customer);
+        Ok(Response::new(rx))
+    }
+}